@@ -1,3 +1,3 @@
-from ._prime_iroh import Node, SendWork, RecvWork
+from ._prime_iroh import Node, NodeConfig, RetryPolicy, ConnectWork, SendWork, RecvWork, RecvIntoWork, RecvAnyWork, RecvTensorWork, RecvTensorsWork, DLPackTensor
 
-__all__ = ["Node", "SendWork", "RecvWork"]
+__all__ = ["Node", "NodeConfig", "RetryPolicy", "ConnectWork", "SendWork", "RecvWork", "RecvIntoWork", "RecvAnyWork", "RecvTensorWork", "RecvTensorsWork", "DLPackTensor"]
@@ -1,4 +1,25 @@
-from typing import Optional
+from typing import Any, Dict, List, Optional, Tuple
+
+class ConnectWork:
+    """A class representing the future of an asynchronous connect operation."""
+    def is_completed(self) -> bool:
+        """Check whether the connect operation has completed, without blocking.
+        
+        Returns:
+            bool: True if the operation has completed (successfully or not)
+        """
+        ...
+
+    def wait(self) -> None:
+        """Wait for the connect operation to complete.
+        
+        Returns:
+            None
+        
+        Raises:
+            RuntimeError: If the operation fails
+        """
+        ...
 
 class SendWork:
     """A class representing the future of an asynchronous send operation."""
@@ -25,16 +46,223 @@ class RecvWork:
             RuntimeError: If the operation fails
         """
         ...
+    
+    def wait_with_seq(self) -> Tuple[bytes, int]:
+        """Wait for the receive operation to complete and return the received data
+        together with its sequence number.
+        
+        Messages of each tag are numbered from 0 in the order they were sent. Gaps
+        (e.g. messages lost with a connection) are logged, while duplicates fail
+        the receive.
+        
+        Returns:
+            Tuple[bytes, int]: The received data and its sequence number
+            
+        Raises:
+            RuntimeError: If the operation fails
+        """
+        ...
+    
+    def wait_dlpack(self) -> DLPackTensor:
+        """Wait for the receive operation to complete and return the received data without copying it.
+        
+        Returns:
+            DLPackTensor: The received data as a one-dimensional uint8 tensor, to be wrapped
+            with e.g. `torch.from_dlpack` or `numpy.from_dlpack`
+            
+        Raises:
+            RuntimeError: If the operation fails
+        """
+        ...
+
+class DLPackTensor:
+    """A received message or tensor implementing the DLPack protocol (on the CPU).
+    
+    It can be wrapped once, e.g. with `torch.from_dlpack`, without copying its data.
+    """
+    def __dlpack__(self, *, stream: Any = None, max_version: Any = None, dl_device: Any = None, copy: Optional[bool] = None) -> Any:
+        """Export the tensor as a DLPack capsule.
+        
+        Raises:
+            BufferError: If the tensor has already been exported or is requested on another device
+        """
+        ...
+    
+    def __dlpack_device__(self) -> Tuple[int, int]:
+        """Return the device of the tensor, which is always the CPU."""
+        ...
+
+class RecvIntoWork:
+    """A class representing the future of an asynchronous receive into a buffer."""
+    def wait(self) -> None:
+        """Wait for the message to be received into the buffer.
+        
+        Raises:
+            RuntimeError: If the operation fails or the message does not match the size of the buffer
+        """
+        ...
+
+class RecvAnyWork:
+    """A class representing the future of an asynchronous receive from any tag."""
+    def wait(self) -> Tuple[int, bytes]:
+        """Wait for the next message of any tag.
+        
+        Returns:
+            Tuple[int, bytes]: The tag the message arrived on and the received data
+            
+        Raises:
+            RuntimeError: If the operation fails
+        """
+        ...
+
+class RecvTensorWork:
+    """A class representing the future of an asynchronous tensor receive operation."""
+    def wait(self) -> Any:
+        """Wait for the tensor to be received.
+        
+        Returns:
+            The received tensor as a numpy array, torch tensor or DLPackTensor, depending on
+            the format passed to `irecv_tensor`
+            
+        Raises:
+            RuntimeError: If the operation fails or the message is not a valid tensor
+        """
+        ...
+
+class RecvTensorsWork:
+    """A class representing the future of an asynchronous receive of several named tensors."""
+    def wait(self) -> Dict[str, Any]:
+        """Wait for the tensors to be received.
+        
+        Returns:
+            A dict from names to the received tensors, as numpy arrays, torch tensors or
+            DLPackTensors depending on the format passed to `irecv_tensors`
+            
+        Raises:
+            RuntimeError: If the operation fails or the message is not a valid safetensors message
+        """
+        ...
+
+class NodeConfig:
+    """Configuration for creating a Node."""
+    
+    def __init__(
+        self,
+        num_streams: int,
+        seed: Optional[int] = None,
+        discovery: str = "n0",
+        relay: bool = True,
+        auto_reconnect: bool = False,
+        allowed_peers: Optional[List[str]] = None,
+        cluster_token: Optional[str] = None,
+        max_recv_streams: Optional[int] = None,
+        max_message_size: Optional[int] = None,
+        compression: str = "none",
+        compression_tags: Optional[Dict[int, str]] = None,
+        compression_threshold: Optional[int] = None,
+        tensor_precision: str = "full",
+        tensor_precision_tags: Optional[Dict[int, str]] = None,
+        checksum: str = "none",
+    ) -> None:
+        """Create a new NodeConfig.
+        
+        Args:
+            num_streams: The number of streams opened when connecting (for tags
+                0 to num_streams - 1), streams of other tags are opened on first use
+            seed: Optional seed for generating the secret/public key
+            discovery: How to resolve peer addresses, either "n0" (n0 discovery
+                service, requires internet access), "local" (mDNS, finds peers on
+                the same local network or host) or "none" (peers have to be
+                connected to via `Node.connect_addr` or a ticket)
+            relay: Whether to use the default n0 relay servers
+            auto_reconnect: Whether to re-dial peers (with the retry policy used
+                to connect) after the connection to them was lost. Works that
+                were in flight fail with a "connection reset" error
+            allowed_peers: Optional node IDs of the peers that are allowed to
+                connect to this node. Connections from any other peer are
+                rejected. By default, any peer can connect
+            cluster_token: Optional shared secret of the cluster. Nodes prove
+                knowledge of it when connecting and reject peers that cannot, so
+                all nodes of a cluster need to use the same token
+            max_recv_streams: Optional maximum number of streams accepted from a
+                peer (defaults to `num_streams`). Peers may connect with any number
+                of streams up to this maximum
+            max_message_size: Optional maximum size of received messages in bytes.
+                Peers fail to send larger messages, and if they send them anyway,
+                receiving fails and only the stream of the message's tag is reset
+            compression: Codec of sent messages, either "none", "zstd" or "lz4".
+                Received messages are decompressed whatever their codec
+            compression_tags: Optional codecs of individual tags, overriding
+                `compression` for messages sent via these tags
+            compression_threshold: Optional size in bytes below which messages are
+                sent uncompressed (defaults to 4096). Messages that don't shrink
+                are sent uncompressed as well
+            tensor_precision: Precision of float32 tensors sent with `isend_tensor`,
+                either "full", "bf16", "fp16" or "fp8" (with a scale factor per block
+                of 128 elements). Received tensors are restored to float32 whatever
+                their precision
+            tensor_precision_tags: Optional precisions of individual tags, overriding
+                `tensor_precision` for tensors sent via these tags
+            checksum: Checksum appended to sent messages, either "none", "crc32c" or
+                "xxh3". Received messages are verified against their checksum (if
+                any), and receives of corrupted messages fail with an error naming
+                the tag and sequence number of the message
+            
+        Raises:
+            RuntimeError: If the discovery mode, an allowed peer ID, a codec, a
+                precision or the checksum is invalid
+        """
+        ...
+    
+    @staticmethod
+    def offline(num_streams: int) -> "NodeConfig":
+        """Create a NodeConfig for networks without internet access, with discovery
+        and relays disabled.
+        
+        Args:
+            num_streams: The number of streams opened when connecting (for tags
+                0 to num_streams - 1), streams of other tags are opened on first use
+            
+        Returns:
+            NodeConfig: A new NodeConfig instance
+        """
+        ...
+
+class RetryPolicy:
+    """Policy for retrying failed connection attempts with exponential backoff."""
+    
+    def __init__(
+        self,
+        initial_delay: float = 0.1,
+        backoff: float = 2.0,
+        jitter: float = 0.1,
+        max_delay: float = 10.0,
+        deadline: Optional[float] = None,
+    ) -> None:
+        """Create a new RetryPolicy.
+        
+        Args:
+            initial_delay: Delay before the first retry in seconds
+            backoff: Factor by which the delay grows after every failed attempt
+            jitter: Fraction by which each delay is randomly shortened or lengthened
+            max_delay: Upper bound for the delay between two attempts in seconds
+            deadline: Optional upper bound for the total time spent connecting in seconds
+            
+        Raises:
+            RuntimeError: If the policy is invalid
+        """
+        ...
 
 class Node:
-    """A class combining a single-peer sender/receiver, allowing to send to exactly one 
-    and receive from exactly one (potentially different) peer."""
+    """A class combining a multi-peer sender/receiver, allowing to send to and receive
+    from any number of peers, keyed by their node ID."""
     
     def __init__(self, num_streams: int) -> None:
         """Create a new Node with a given number of micro-batches.
         
         Args:
-            num_streams: The number of parallel streams to use
+            num_streams: The number of streams opened when connecting (for tags
+                0 to num_streams - 1), streams of other tags are opened on first use
             
         Raises:
             RuntimeError: If node creation fails
@@ -46,7 +274,8 @@ class Node:
         """Create a new Node with a given number of micro-batches and fixed seed.
         
         Args:
-            num_streams: The number of parallel streams to use
+            num_streams: The number of streams opened when connecting (for tags
+                0 to num_streams - 1), streams of other tags are opened on first use
             seed: Optional seed for generating the secret/public key
             
         Returns:
@@ -57,6 +286,21 @@ class Node:
         """
         ...
     
+    @staticmethod
+    def with_config(config: NodeConfig) -> "Node":
+        """Create a new Node from a NodeConfig.
+        
+        Args:
+            config: The configuration of the Node
+            
+        Returns:
+            Node: A new Node instance
+            
+        Raises:
+            RuntimeError: If node creation fails
+        """
+        ...
+    
     def node_id(self) -> str:
         """Get the node ID of the Node.
         
@@ -65,18 +309,117 @@ class Node:
         """
         ...
     
-    def connect(self, peer_id_str: str, num_retries: int) -> None:
-        """Connect to a Node with a given node ID.
+    def ticket(self) -> str:
+        """Get a ticket containing the node ID, direct addresses and relay URL of the
+        Node, which peers can pass to `connect` to connect without discovery.
+        
+        Returns:
+            str: The ticket as a string
+        """
+        ...
+    
+    def direct_addresses(self) -> List[str]:
+        """Get the socket addresses the Node can be reached at directly.
+        
+        Returns:
+            List[str]: The socket addresses as strings, e.g. "192.168.1.2:4242"
+        """
+        ...
+    
+    def relay_url(self) -> Optional[str]:
+        """Get the URL of the home relay server of the Node.
+        
+        Returns:
+            Optional[str]: The relay URL, or None if relays are disabled
+        """
+        ...
+    
+    def connect(
+        self,
+        peer_id_str: str,
+        num_retries: int,
+        retry_policy: Optional[RetryPolicy] = None,
+    ) -> None:
+        """Connect to a Node with a given node ID or ticket. Can be called multiple
+        times to connect to several peers.
+        
+        Args:
+            peer_id_str: The ID or ticket of the peer to connect to
+            num_retries: The number of retries to attempt
+            retry_policy: Optional policy for the delay between retries, defaults
+                to exponential backoff starting at 0.1s
+            
+        Raises:
+            RuntimeError: If connection fails
+        """
+        ...
+    
+    def connect_addr(
+        self,
+        peer_id_str: str,
+        direct_addresses: List[str],
+        num_retries: int,
+        relay_url: Optional[str] = None,
+        retry_policy: Optional[RetryPolicy] = None,
+    ) -> None:
+        """Connect to a Node with a given node ID and full address. This does not
+        require discovery, so it works on networks without internet access.
         
         Args:
             peer_id_str: The ID of the peer to connect to
+            direct_addresses: The socket addresses the peer can be reached at
             num_retries: The number of retries to attempt
+            relay_url: Optional URL of the peer's home relay server
+            retry_policy: Optional policy for the delay between retries, defaults
+                to exponential backoff starting at 0.1s
             
         Raises:
             RuntimeError: If connection fails
         """
         ...
     
+    def iconnect(
+        self,
+        peer_id_str: str,
+        num_retries: int,
+        retry_policy: Optional[RetryPolicy] = None,
+    ) -> ConnectWork:
+        """Connect to a Node with a given node ID or ticket without blocking.
+        
+        Args:
+            peer_id_str: The ID or ticket of the peer to connect to
+            num_retries: The number of retries to attempt
+            retry_policy: Optional policy for the delay between retries, defaults
+                to exponential backoff starting at 0.1s
+            
+        Returns:
+            ConnectWork: A ConnectWork object representing the async operation
+        """
+        ...
+    
+    def iconnect_addr(
+        self,
+        peer_id_str: str,
+        direct_addresses: List[str],
+        num_retries: int,
+        relay_url: Optional[str] = None,
+        retry_policy: Optional[RetryPolicy] = None,
+    ) -> ConnectWork:
+        """Connect to a Node with a given node ID and full address without blocking.
+        
+        Args:
+            peer_id_str: The ID of the peer to connect to
+            direct_addresses: The socket addresses the peer can be reached at
+            num_retries: The number of retries to attempt
+            relay_url: Optional URL of the peer's home relay server
+            retry_policy: Optional policy for the delay between retries, defaults
+                to exponential backoff starting at 0.1s
+            
+        Returns:
+            ConnectWork: A ConnectWork object representing the async operation
+        """
+        ...
+    
     def can_recv(self) -> bool:
         """Check if the Node can receive messages.
         
@@ -101,12 +444,59 @@ class Node:
         """
         ...
     
-    def isend(self, msg: bytes, tag: int, latency: Optional[int] = None) -> SendWork:
-        """Send a message to a Node with a given tag.
+    def send_peers(self) -> List[str]:
+        """Get the node IDs of all peers the Node is connected to for sending.
+        
+        Returns:
+            List[str]: The node IDs as hex strings
+        """
+        ...
+    
+    def recv_peers(self) -> List[str]:
+        """Get the node IDs of all peers that are connected to the Node for receiving.
+        
+        Returns:
+            List[str]: The node IDs as hex strings
+        """
+        ...
+    
+    def isend(self, msg: Any, tag: int, latency: Optional[int] = None) -> SendWork:
+        """Send a message to the only connected peer with a given tag.
+        
+        Messages with the same tag are sent in the order of the `isend` calls.
+        The message is sent directly from its memory without copying it, so it
+        must not be modified until the returned work completes.
         
         Args:
-            msg: The message to send as bytes
-            tag: The tag to send the message to
+            msg: The message to send, any C-contiguous object supporting the buffer
+                protocol (e.g. bytes, bytearray, memoryview or a numpy array) or a
+                CPU tensor
+            tag: The tag to send the message to (any non-negative integer)
+            latency: Optional latency in milliseconds
+            
+        Returns:
+            SendWork: A SendWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If sending fails
+        """
+        ...
+    
+    def isend_to(
+        self, peer_id_str: str, msg: Any, tag: int, latency: Optional[int] = None
+    ) -> SendWork:
+        """Send a message to the peer with a given node ID and tag.
+        
+        Messages with the same peer and tag are sent in the order of the calls.
+        The message is sent directly from its memory without copying it, so it
+        must not be modified until the returned work completes.
+        
+        Args:
+            peer_id_str: The ID of the peer to send to
+            msg: The message to send, any C-contiguous object supporting the buffer
+                protocol (e.g. bytes, bytearray, memoryview or a numpy array) or a
+                CPU tensor
+            tag: The tag to send the message to (any non-negative integer)
             latency: Optional latency in milliseconds
             
         Returns:
@@ -118,7 +508,9 @@ class Node:
         ...
     
     def irecv(self, tag: int) -> RecvWork:
-        """Receive a message from a Node with a given tag.
+        """Receive a message from the only connected peer with a given tag.
+        
+        Messages with the same tag are received in the order of the `irecv` calls.
         
         Args:
             tag: The tag to receive the message from
@@ -131,6 +523,293 @@ class Node:
         """
         ...
     
+    def irecv_from(self, peer_id_str: str, tag: int) -> RecvWork:
+        """Receive a message from the peer with a given node ID and tag.
+        
+        Messages with the same peer and tag are received in the order of the calls.
+        
+        Args:
+            peer_id_str: The ID of the peer to receive from
+            tag: The tag to receive the message from
+            
+        Returns:
+            RecvWork: A RecvWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If receiving fails
+        """
+        ...
+    
+    def irecv_into(self, tag: int, buffer: Any) -> RecvIntoWork:
+        """Receive a message from the only connected peer with a given tag into a buffer.
+        
+        The message is written directly into the memory of the buffer, which has to be a
+        writable, C-contiguous object supporting the buffer protocol (e.g. a bytearray or
+        numpy array) or a CPU tensor of exactly the size of the message.
+        
+        Args:
+            tag: The tag to receive the message from
+            buffer: The buffer to receive the message into
+            
+        Returns:
+            RecvIntoWork: A RecvIntoWork object representing the async operation
+            
+        Raises:
+            TypeError: If the buffer is read-only or does not support the buffer protocol
+            RuntimeError: If receiving fails
+        """
+        ...
+    
+    def irecv_from_into(self, peer_id_str: str, tag: int, buffer: Any) -> RecvIntoWork:
+        """Receive a message from the peer with a given node ID and tag into a buffer.
+        
+        Args:
+            peer_id_str: The ID of the peer to receive from
+            tag: The tag to receive the message from
+            buffer: The buffer to receive the message into
+            
+        Returns:
+            RecvIntoWork: A RecvIntoWork object representing the async operation
+            
+        Raises:
+            TypeError: If the buffer is read-only or does not support the buffer protocol
+            RuntimeError: If receiving fails
+        """
+        ...
+    
+    def irecv_any(self) -> RecvAnyWork:
+        """Receive the next message of whichever tag it arrives on first from the only
+        connected peer.
+        
+        Messages of tags that other receives are already waiting for are left to them.
+        
+        Returns:
+            RecvAnyWork: A RecvAnyWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If receiving fails
+        """
+        ...
+    
+    def irecv_any_from(self, peer_id_str: str) -> RecvAnyWork:
+        """Receive the next message of whichever tag it arrives on first from the peer
+        with a given node ID.
+        
+        Args:
+            peer_id_str: The ID of the peer to receive from
+            
+        Returns:
+            RecvAnyWork: A RecvAnyWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If receiving fails
+        """
+        ...
+    
+    def iprobe(self, tag: int) -> Optional[int]:
+        """Check whether the next message of a tag from the only connected peer has
+        arrived, without receiving it or blocking.
+        
+        Messages that earlier receives of the tag are waiting for are not reported.
+        
+        Args:
+            tag: The tag to check
+            
+        Returns:
+            Optional[int]: The size of the message once received, or None if no message is pending
+            
+        Raises:
+            RuntimeError: If the message header is invalid or the connection was lost
+        """
+        ...
+    
+    def iprobe_from(self, peer_id_str: str, tag: int) -> Optional[int]:
+        """Check whether the next message of a tag from the peer with a given node ID
+        has arrived, without receiving it or blocking.
+        
+        Args:
+            peer_id_str: The ID of the peer to check
+            tag: The tag to check
+            
+        Returns:
+            Optional[int]: The size of the message once received, or None if no message is pending
+            
+        Raises:
+            RuntimeError: If the message header is invalid or the connection was lost
+        """
+        ...
+    
+    def probe(self, tag: int) -> int:
+        """Wait for the next message of a tag from the only connected peer, without
+        receiving it.
+        
+        The message is the one that the next `irecv` of the tag returns.
+        
+        Args:
+            tag: The tag to wait for
+            
+        Returns:
+            int: The size of the message once received
+            
+        Raises:
+            RuntimeError: If the message header is invalid or the connection was lost
+        """
+        ...
+    
+    def probe_from(self, peer_id_str: str, tag: int) -> int:
+        """Wait for the next message of a tag from the peer with a given node ID, without
+        receiving it.
+        
+        Args:
+            peer_id_str: The ID of the peer to wait for
+            tag: The tag to wait for
+            
+        Returns:
+            int: The size of the message once received
+            
+        Raises:
+            RuntimeError: If the message header is invalid or the connection was lost
+        """
+        ...
+    
+    def isend_tensor(self, tensor: Any, tag: int, latency: Optional[int] = None) -> SendWork:
+        """Send a numpy array or torch tensor to the only connected peer with a given tag.
+        
+        The tensor is sent together with its dtype and shape, so that the receiver can
+        restore it with `irecv_tensor`. Torch tensors are moved to the CPU first.
+        Float32 tensors are downcast to the `tensor_precision` of the tag.
+        
+        Args:
+            tensor: The numpy array or torch tensor to send
+            tag: The tag to send the tensor with
+            latency: Optional latency to simulate in milliseconds
+            
+        Returns:
+            SendWork: A SendWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the dtype is not supported or sending fails
+        """
+        ...
+    
+    def isend_tensor_to(self, peer_id_str: str, tensor: Any, tag: int, latency: Optional[int] = None) -> SendWork:
+        """Send a numpy array or torch tensor to the peer with a given node ID and tag.
+        
+        Args:
+            peer_id_str: The ID of the peer to send to
+            tensor: The numpy array or torch tensor to send
+            tag: The tag to send the tensor with
+            latency: Optional latency to simulate in milliseconds
+            
+        Returns:
+            SendWork: A SendWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the dtype is not supported or sending fails
+        """
+        ...
+    
+    def isend_tensors(self, tensors: Dict[str, Any], tag: int, latency: Optional[int] = None) -> SendWork:
+        """Send several named numpy arrays or torch tensors to the only connected peer as one message.
+        
+        The tensors are packed in the safetensors layout (a JSON header followed by the
+        contiguous data of all tensors), which `irecv_tensors` validates and unpacks.
+        
+        Args:
+            tensors: A dict from names to the numpy arrays or torch tensors to send
+            tag: The tag to send the tensors with
+            latency: Optional latency to simulate in milliseconds
+            
+        Returns:
+            SendWork: A SendWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If a dtype is not supported or sending fails
+        """
+        ...
+    
+    def isend_tensors_to(self, peer_id_str: str, tensors: Dict[str, Any], tag: int, latency: Optional[int] = None) -> SendWork:
+        """Send several named numpy arrays or torch tensors to the peer with a given node ID as one message.
+        
+        Args:
+            peer_id_str: The ID of the peer to send to
+            tensors: A dict from names to the numpy arrays or torch tensors to send
+            tag: The tag to send the tensors with
+            latency: Optional latency to simulate in milliseconds
+            
+        Returns:
+            SendWork: A SendWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If a dtype is not supported or sending fails
+        """
+        ...
+    
+    def irecv_tensors(self, tag: int, format: str = "numpy") -> RecvTensorsWork:
+        """Receive tensors sent with `isend_tensors` from the only connected peer with a given tag.
+        
+        Args:
+            tag: The tag to receive the tensors from
+            format: Type of the received tensors, "numpy", "torch" or "dlpack" (see `irecv_tensor`)
+            
+        Returns:
+            RecvTensorsWork: A RecvTensorsWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the format is invalid or receiving fails
+        """
+        ...
+    
+    def irecv_tensors_from(self, peer_id_str: str, tag: int, format: str = "numpy") -> RecvTensorsWork:
+        """Receive tensors sent with `isend_tensors` from the peer with a given node ID and tag.
+        
+        Args:
+            peer_id_str: The ID of the peer to receive from
+            tag: The tag to receive the tensors from
+            format: Type of the received tensors, "numpy", "torch" or "dlpack" (see `irecv_tensor`)
+            
+        Returns:
+            RecvTensorsWork: A RecvTensorsWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the format is invalid or receiving fails
+        """
+        ...
+    
+    def irecv_tensor(self, tag: int, format: str = "numpy") -> RecvTensorWork:
+        """Receive a tensor sent with `isend_tensor` from the only connected peer with a given tag.
+        
+        Args:
+            tag: The tag to receive the tensor from
+            format: Type of the received tensor, "numpy" for numpy arrays, "torch" for torch tensors or
+                "dlpack" for a DLPackTensor that can be wrapped without copying
+            
+        Returns:
+            RecvTensorWork: A RecvTensorWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the format is invalid or receiving fails (also for bfloat16 tensors received
+                as numpy arrays, which numpy does not support)
+        """
+        ...
+    
+    def irecv_tensor_from(self, peer_id_str: str, tag: int, format: str = "numpy") -> RecvTensorWork:
+        """Receive a tensor sent with `isend_tensor` from the peer with a given node ID and tag.
+        
+        Args:
+            peer_id_str: The ID of the peer to receive from
+            tag: The tag to receive the tensor from
+            format: Type of the received tensor, "numpy" for numpy arrays, "torch" for torch tensors or
+                "dlpack" for a DLPackTensor that can be wrapped without copying
+            
+        Returns:
+            RecvTensorWork: A RecvTensorWork object representing the async operation
+            
+        Raises:
+            RuntimeError: If the format is invalid or receiving fails
+        """
+        ...
+    
     def close(self) -> None:
         """Close the Node.
         
@@ -1,4 +1,4 @@
-from prime_iroh import Node
+from prime_iroh import Node, NodeConfig
 import time
 
 NUM_MESSAGES = 5
@@ -6,16 +6,16 @@ NUM_STREAMS = 1
 
 class BidirectionalTest:
     def __init__(self):
-        # Initialize nodes
-        self.node0 = Node.with_seed(NUM_STREAMS, None)
-        self.node1 = Node.with_seed(NUM_STREAMS, None)
+        # Initialize nodes (offline, so that the test does not depend on discovery)
+        self.node0 = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+        self.node1 = Node.with_config(NodeConfig.offline(NUM_STREAMS))
         
         # Wait for nodes to initialize (only necessary in single process tests)
         time.sleep(1)
 
         # Connect bidirectionally
-        self.node0.connect(self.node1.node_id(), 10)
-        self.node1.connect(self.node0.node_id(), 10)
+        self.node0.connect(self.node1.ticket(), 10)
+        self.node1.connect(self.node0.ticket(), 10)
         
         # Wait for connection to be established
         while not self.node0.can_recv() or not self.node1.can_send():
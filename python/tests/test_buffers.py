@@ -0,0 +1,108 @@
+import pytest
+from prime_iroh import Node, NodeConfig
+import time
+
+NUM_STREAMS = 1
+
+class BufferTest:
+    def __init__(self):
+        # Initialize receiver (offline, so that the test does not depend on discovery)
+        self.receiver = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+
+        # Wait for nodes to initialize (only necessary in single process tests)
+        time.sleep(1)
+
+        # Initialize sender
+        self.sender = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+        self.sender.connect_addr(self.receiver.node_id(), self.receiver.direct_addresses(), 10)
+
+        # Wait for connection to be established
+        while not self.receiver.can_recv() or not self.sender.can_send():
+            time.sleep(0.1)
+
+    def send_and_receive(self, msg):
+        self.sender.isend(msg, tag=0, latency=None).wait()
+        return self.receiver.irecv(tag=0).wait()
+
+def test_buffer_messages():
+    test = BufferTest()
+
+    # Objects supporting the buffer protocol are sent as their raw bytes
+    assert test.send_and_receive(b"bytes") == b"bytes"
+    assert test.send_and_receive(bytearray(b"bytearray")) == b"bytearray"
+    assert test.send_and_receive(memoryview(b"memoryview")) == b"memoryview"
+
+    # Objects without the buffer protocol are rejected
+    with pytest.raises(TypeError):
+        test.sender.isend("str", tag=0, latency=None)
+
+def test_numpy_messages():
+    np = pytest.importorskip("numpy")
+    test = BufferTest()
+
+    array = np.arange(16, dtype=np.float32).reshape(4, 4)
+    recv = test.send_and_receive(array)
+    assert np.array_equal(np.frombuffer(recv, dtype=np.float32).reshape(4, 4), array)
+
+    # Non-contiguous arrays cannot be sent without copying them
+    with pytest.raises(Exception):
+        test.sender.isend(array[:, 0], tag=0, latency=None)
+
+def test_torch_messages():
+    torch = pytest.importorskip("torch")
+    test = BufferTest()
+
+    tensor = torch.arange(16, dtype=torch.float32)
+    recv = test.send_and_receive(tensor)
+    assert torch.equal(torch.frombuffer(bytearray(recv), dtype=torch.float32), tensor)
+
+    # Also tensors that numpy cannot represent, or that require grad
+    tensor = torch.randn(4, 4, dtype=torch.bfloat16, requires_grad=True)
+    recv = test.send_and_receive(tensor)
+    assert torch.equal(torch.frombuffer(bytearray(recv), dtype=torch.bfloat16).reshape(4, 4), tensor)
+
+    # Non-contiguous tensors cannot be sent without copying them
+    with pytest.raises(TypeError):
+        test.sender.isend(tensor.T, tag=0, latency=None)
+
+def test_receive_into_bfloat16_tensor():
+    torch = pytest.importorskip("torch")
+    test = BufferTest()
+
+    tensor = torch.randn(4, 4, dtype=torch.bfloat16)
+    recv = torch.zeros(4, 4, dtype=torch.bfloat16)
+    test.sender.isend(tensor, tag=0, latency=None).wait()
+    test.receiver.irecv_into(0, recv).wait()
+    assert torch.equal(recv, tensor)
+
+def test_receive_into_buffers():
+    test = BufferTest()
+
+    # Messages are received directly into writable buffers
+    buffer = bytearray(4)
+    test.sender.isend(b"into", tag=0, latency=None).wait()
+    test.receiver.irecv_into(0, buffer).wait()
+    assert buffer == b"into"
+
+    # Messages that don't match the size of the buffer are dropped
+    test.sender.isend(b"mismatch", tag=0, latency=None).wait()
+    test.sender.isend(b"next", tag=0, latency=None).wait()
+    with pytest.raises(RuntimeError):
+        test.receiver.irecv_into(0, bytearray(4)).wait()
+    assert buffer == b"into"
+    test.receiver.irecv_into(0, buffer).wait()
+    assert buffer == b"next"
+
+    # Read-only buffers are rejected
+    with pytest.raises(TypeError):
+        test.receiver.irecv_into(0, b"read")
+
+def test_receive_into_numpy():
+    np = pytest.importorskip("numpy")
+    test = BufferTest()
+
+    array = np.arange(16, dtype=np.float32)
+    recv = np.zeros(16, dtype=np.float32)
+    test.sender.isend(array, tag=0, latency=None).wait()
+    test.receiver.irecv_into(0, recv).wait()
+    assert np.array_equal(recv, array)
@@ -0,0 +1,52 @@
+import pytest
+from prime_iroh import Node, NodeConfig
+import time
+
+NUM_STREAMS = 2
+
+def test_compressed_messages():
+    # Initialize receiver and a sender compressing tag 0 with zstd and tag 1 with lz4
+    receiver = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+    time.sleep(1)
+    config = NodeConfig(
+        NUM_STREAMS,
+        discovery="none",
+        relay=False,
+        compression="zstd",
+        compression_tags={1: "lz4"},
+        compression_threshold=1024,
+    )
+    sender = Node.with_config(config)
+    sender.connect_addr(receiver.node_id(), receiver.direct_addresses(), 10)
+    while not receiver.can_recv() or not sender.can_send():
+        time.sleep(0.1)
+
+    # Messages are decompressed transparently, whatever their codec or size
+    msg = bytes(i % 13 for i in range(65536))
+    for tag in range(NUM_STREAMS):
+        sender.isend(msg, tag=tag, latency=None).wait()
+        assert receiver.irecv(tag=tag).wait() == msg
+        sender.isend(b"small", tag=tag, latency=None).wait()
+        assert receiver.irecv(tag=tag).wait() == b"small"
+
+def test_checksummed_messages():
+    receiver = Node.with_config(NodeConfig.offline(1))
+    time.sleep(1)
+    sender = Node.with_config(NodeConfig(1, discovery="none", relay=False, checksum="xxh3", compression="lz4"))
+    sender.connect_addr(receiver.node_id(), receiver.direct_addresses(), 10)
+    while not receiver.can_recv() or not sender.can_send():
+        time.sleep(0.1)
+
+    # Messages are verified transparently, also when compressed
+    for msg in [b"small", bytes(65536)]:
+        sender.isend(msg, tag=0, latency=None).wait()
+        assert receiver.irecv(tag=0).wait() == msg
+
+    with pytest.raises(RuntimeError):
+        NodeConfig(1, checksum="md5")
+
+def test_invalid_compression():
+    with pytest.raises(RuntimeError):
+        NodeConfig(1, compression="gzip")
+    with pytest.raises(RuntimeError):
+        NodeConfig(1, compression_tags={0: "gzip"})
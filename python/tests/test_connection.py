@@ -1,15 +1,19 @@
-from prime_iroh import Node
+import os
+import pytest
+from prime_iroh import Node, NodeConfig
 import time
 
 NUM_STREAMS = 1
 
 class ConnectionTest:
-    def __init__(self, num_nodes):
-        # Initialize nodes
+    def __init__(self, num_nodes, new_node=None):
+        # Initialize nodes (with local network discovery by default, so that the test does not depend on internet access)
+        if new_node is None:
+            new_node = lambda: Node.with_config(NodeConfig(NUM_STREAMS, discovery="local", relay=False))
         self.nodes = []
         
         for i in range(num_nodes):
-            node = Node(num_streams=NUM_STREAMS)
+            node = new_node()
             node_id = node.node_id()
             print(f"Initializing node {i} (ID: {node_id})")
             self.nodes.append(node)
@@ -17,14 +21,18 @@ class ConnectionTest:
         # Wait for nodes to initialize (only necessary in single process tests)
         time.sleep(1)
         
-        # Connect nodes
+        # Connect nodes concurrently
+        connect_works = []
         for i in range(num_nodes):
             current_node = self.nodes[i]
             j = (i + 1) % num_nodes
             node_id = current_node.node_id()
             peer_id = self.nodes[j].node_id()
             print(f"Connecting node {i}->{j} (ID: {node_id}->{peer_id})")
-            current_node.connect(peer_id, 10)
+            connect_works.append(current_node.iconnect(peer_id, 10))
+        for connect_work in connect_works:
+            connect_work.wait()
+        assert all(connect_work.is_completed() for connect_work in connect_works)
         
         # Wait for all nodes to be ready
         while not all(node.is_ready() for node in self.nodes):
@@ -60,4 +68,19 @@ def test_connection():
     test.teardown()
     
     # Test connection state
-    test.verify_inactive_connection_state() 
\ No newline at end of file
+    test.verify_inactive_connection_state() 
+
+@pytest.mark.skipif(not os.environ.get("PRIME_IROH_NETWORK_TESTS"), reason="requires internet access")
+def test_connection_via_n0_discovery():
+    # Default nodes, which find each other by node ID via n0 discovery and relays
+    num_nodes = 2
+    test = ConnectionTest(num_nodes, new_node=lambda: Node(NUM_STREAMS))
+    
+    # Test connection state and a message over the connection
+    test.verify_active_connection_state()
+    test.nodes[0].isend(b"via n0", tag=0, latency=None).wait()
+    assert test.nodes[1].irecv(tag=0).wait() == b"via n0"
+    
+    # Teardown
+    test.teardown()
+    test.verify_inactive_connection_state()
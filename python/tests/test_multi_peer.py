@@ -0,0 +1,67 @@
+import pytest
+from prime_iroh import Node, NodeConfig
+import time
+
+NUM_MESSAGES = 5
+NUM_STREAMS = 1
+NUM_SPOKES = 2
+
+class MultiPeerTest:
+    def __init__(self, num_spokes):
+        # Initialize hub and spokes (offline, so that the test does not depend on discovery)
+        self.hub = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+        self.spokes = [Node.with_config(NodeConfig.offline(NUM_STREAMS)) for _ in range(num_spokes)]
+
+        # Wait for nodes to initialize (only necessary in single process tests)
+        time.sleep(1)
+
+        # Connect hub and spokes bidirectionally
+        for spoke in self.spokes:
+            self.hub.connect_addr(spoke.node_id(), spoke.direct_addresses(), 10)
+            spoke.connect_addr(self.hub.node_id(), self.hub.direct_addresses(), 10)
+
+        # Wait for all connections to be established
+        while len(self.hub.recv_peers()) < num_spokes or not all(spoke.is_ready() for spoke in self.spokes):
+            time.sleep(0.1)
+
+    def test_peers(self):
+        spoke_ids = sorted(spoke.node_id() for spoke in self.spokes)
+        assert sorted(self.hub.send_peers()) == spoke_ids
+        assert sorted(self.hub.recv_peers()) == spoke_ids
+
+    def test_ambiguous_peer(self):
+        # Hub has multiple peers, so the peer has to be specified explicitly
+        with pytest.raises(RuntimeError):
+            self.hub.isend(b"ambiguous", tag=0, latency=None).wait()
+        with pytest.raises(RuntimeError):
+            self.hub.irecv(tag=0).wait()
+
+    def test_communication(self):
+        for i in range(NUM_MESSAGES):
+            # Hub sends a different message to each spoke
+            for j, spoke in enumerate(self.spokes):
+                msg = f"Message {i} from hub to spoke {j}"
+                self.hub.isend_to(spoke.node_id(), msg.encode(), tag=0).wait()
+
+            # Each spoke receives its message and replies
+            for j, spoke in enumerate(self.spokes):
+                recv = spoke.irecv(tag=0).wait().decode()
+                assert recv == f"Message {i} from hub to spoke {j}"
+                spoke.isend(f"Message {i} from spoke {j} to hub".encode(), tag=0, latency=None).wait()
+
+            # Hub receives the reply from each spoke
+            for j, spoke in enumerate(self.spokes):
+                recv = self.hub.irecv_from(spoke.node_id(), tag=0).wait().decode()
+                assert recv == f"Message {i} from spoke {j} to hub"
+
+def test_multi_peer_communication():
+    test = MultiPeerTest(NUM_SPOKES)
+
+    # Test peer bookkeeping
+    test.test_peers()
+
+    # Test that implicit peer selection fails with multiple peers
+    test.test_ambiguous_peer()
+
+    # Test communication with each peer
+    test.test_communication()
@@ -0,0 +1,184 @@
+import pytest
+from prime_iroh import Node, NodeConfig
+import time
+
+NUM_STREAMS = 1
+
+class TensorTest:
+    def __init__(self, sender_config=None):
+        # Initialize receiver (offline, so that the test does not depend on discovery)
+        self.receiver = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+
+        # Wait for nodes to initialize (only necessary in single process tests)
+        time.sleep(1)
+
+        # Initialize sender
+        self.sender = Node.with_config(sender_config or NodeConfig.offline(NUM_STREAMS))
+        self.sender.connect_addr(self.receiver.node_id(), self.receiver.direct_addresses(), 10)
+
+        # Wait for connection to be established
+        while not self.receiver.can_recv() or not self.sender.can_send():
+            time.sleep(0.1)
+
+    def send_and_receive(self, tensor, format):
+        self.sender.isend_tensor(tensor, tag=0).wait()
+        return self.receiver.irecv_tensor(tag=0, format=format).wait()
+
+def test_invalid_tensors():
+    test = TensorTest()
+
+    # Raw messages are not mistaken for tensors
+    test.sender.isend(b"not a tensor", tag=0, latency=None).wait()
+    with pytest.raises(RuntimeError):
+        test.receiver.irecv_tensor(tag=0).wait()
+
+    # Tensors can only be converted into numpy arrays or torch tensors
+    with pytest.raises(RuntimeError):
+        test.receiver.irecv_tensor(tag=0, format="jax")
+
+def test_numpy_tensors():
+    np = pytest.importorskip("numpy")
+    test = TensorTest()
+
+    # Dtype and shape are restored, also for non-contiguous and big-endian arrays
+    for array in [
+        np.arange(24, dtype=np.float32).reshape(2, 3, 4),
+        np.arange(12, dtype=np.int64).reshape(3, 4).T,
+        np.arange(6, dtype=">f8"),
+        np.array(True),
+        np.zeros((0, 4), dtype=np.float16),
+    ]:
+        recv = test.send_and_receive(array, "numpy")
+        assert recv.shape == array.shape
+        assert recv.dtype.name == array.dtype.name
+        assert np.array_equal(recv, array)
+
+def test_downcast_tensors():
+    np = pytest.importorskip("numpy")
+    config = NodeConfig(NUM_STREAMS, discovery="none", relay=False, tensor_precision="fp8")
+    test = TensorTest(config)
+
+    # Float32 tensors are restored to float32, at the cost of some precision
+    array = np.linspace(-1, 1, 1024, dtype=np.float32).reshape(32, 32)
+    recv = test.send_and_receive(array, "numpy")
+    assert recv.dtype == np.float32
+    assert np.allclose(recv, array, atol=1 / 16)
+
+    # Probes report the size of the restored tensor, which fits the buffer of irecv_into
+    test.sender.isend_tensor(array, tag=0).wait()
+    buffer = bytearray(test.receiver.probe(tag=0))
+    test.receiver.irecv_into(0, buffer).wait()
+
+    # Tensors of other dtypes are sent as is
+    array = np.arange(16, dtype=np.int32)
+    assert np.array_equal(test.send_and_receive(array, "numpy"), array)
+
+def test_invalid_precision():
+    with pytest.raises(RuntimeError):
+        NodeConfig(NUM_STREAMS, tensor_precision="fp4")
+
+def test_torch_tensors():
+    torch = pytest.importorskip("torch")
+    test = TensorTest()
+
+    # Also works for dtypes that numpy does not support
+    for tensor in [
+        torch.randn(4, 8),
+        torch.randn(4, 8, dtype=torch.bfloat16),
+        torch.arange(12).reshape(3, 4).T,
+    ]:
+        recv = test.send_and_receive(tensor, "torch")
+        assert recv.dtype == tensor.dtype
+        assert torch.equal(recv, tensor)
+
+def test_dlpack_export():
+    test = TensorTest()
+
+    # Received messages implement the DLPack protocol (for the CPU)
+    test.sender.isend(b"dlpack", tag=0, latency=None).wait()
+    tensor = test.receiver.irecv(tag=0).wait_dlpack()
+    assert tensor.__dlpack_device__() == (1, 0)
+
+    # The tensor can only be exported once, unconsumed exports are freed
+    capsule = tensor.__dlpack__()
+    assert type(capsule).__name__ == "PyCapsule"
+    with pytest.raises(BufferError):
+        tensor.__dlpack__()
+    del capsule
+
+def test_dlpack_torch():
+    torch = pytest.importorskip("torch")
+    test = TensorTest()
+
+    # Raw messages are wrapped as uint8 tensors
+    test.sender.isend(b"dlpack", tag=0, latency=None).wait()
+    recv = torch.from_dlpack(test.receiver.irecv(tag=0).wait_dlpack())
+    assert bytes(recv.tolist()) == b"dlpack"
+
+    # Tensors keep their dtype and shape
+    tensor = torch.randn(4, 8, dtype=torch.bfloat16)
+    test.sender.isend_tensor(tensor, tag=0).wait()
+    recv = torch.from_dlpack(test.receiver.irecv_tensor(tag=0, format="dlpack").wait())
+    assert torch.equal(recv, tensor)
+
+def test_bfloat16_as_numpy():
+    torch = pytest.importorskip("torch")
+    test = TensorTest()
+
+    # numpy has no bfloat16 dtype, so the receive fails with a hint at the other formats
+    test.sender.isend_tensor(torch.randn(4, 8, dtype=torch.bfloat16), tag=0).wait()
+    with pytest.raises(RuntimeError, match="format=\"torch\""):
+        test.receiver.irecv_tensor(tag=0, format="numpy").wait()
+
+def test_dlpack_numpy():
+    np = pytest.importorskip("numpy")
+    test = TensorTest()
+
+    array = np.arange(12, dtype=np.int32).reshape(3, 4)
+    test.sender.isend_tensor(array, tag=0).wait()
+    recv = np.from_dlpack(test.receiver.irecv_tensor(tag=0, format="dlpack").wait())
+    assert np.array_equal(recv, array)
+
+def test_invalid_tensors_messages():
+    test = TensorTest()
+
+    # Raw messages are not mistaken for safetensors messages
+    test.sender.isend(b"not safetensors", tag=0, latency=None).wait()
+    with pytest.raises(RuntimeError):
+        test.receiver.irecv_tensors(tag=0).wait()
+
+def test_numpy_tensors_messages():
+    np = pytest.importorskip("numpy")
+    test = TensorTest()
+
+    tensors = {
+        "hidden_states": np.random.randn(2, 8).astype(np.float32),
+        "attention_mask": np.ones((2, 8), dtype=bool),
+        "positions": np.arange(8, dtype=np.int64),
+    }
+    test.sender.isend_tensors(tensors, tag=0).wait()
+    recv = test.receiver.irecv_tensors(tag=0).wait()
+    assert sorted(recv) == sorted(tensors)
+    for name, array in tensors.items():
+        assert recv[name].dtype == array.dtype
+        assert np.array_equal(recv[name], array)
+
+def test_torch_tensors_messages():
+    torch = pytest.importorskip("torch")
+    test = TensorTest()
+
+    tensors = {
+        "hidden_states": torch.randn(2, 8, dtype=torch.bfloat16),
+        "residual": torch.randn(2, 8),
+    }
+    test.sender.isend_tensors(tensors, tag=0).wait()
+    recv = test.receiver.irecv_tensors(tag=0, format="torch").wait()
+    for name, tensor in tensors.items():
+        assert torch.equal(recv[name], tensor)
+
+    # The message can be read by the safetensors library
+    safetensors = pytest.importorskip("safetensors.torch")
+    test.sender.isend_tensors(tensors, tag=0).wait()
+    recv = safetensors.load(test.receiver.irecv(tag=0).wait())
+    for name, tensor in tensors.items():
+        assert torch.equal(recv[name], tensor)
@@ -1,5 +1,5 @@
 import pytest
-from prime_iroh import Node
+from prime_iroh import Node, NodeConfig
 import time
 
 NUM_MESSAGES = 5
@@ -7,15 +7,15 @@ NUM_STREAMS = 1
 
 class UnidirectionalTest:
     def __init__(self):
-        # Initialize receiver
-        self.receiver = Node(num_streams=NUM_STREAMS)
+        # Initialize receiver (offline, so that the test does not depend on discovery)
+        self.receiver = Node.with_config(NodeConfig.offline(NUM_STREAMS))
         
         # Wait for nodes to initialize (only necessary in single process tests)
         time.sleep(1)
         
         # Initialize sender
-        self.sender = Node(num_streams=NUM_STREAMS)
-        self.sender.connect(self.receiver.node_id(), 10)
+        self.sender = Node.with_config(NodeConfig.offline(NUM_STREAMS))
+        self.sender.connect_addr(self.receiver.node_id(), self.receiver.direct_addresses(), 10)
         
         # Wait for connection to be established
         while not self.receiver.can_recv() or not self.sender.can_send():
@@ -47,6 +47,32 @@ class UnidirectionalTest:
 
             sent.wait()
 
+    def test_sequence_numbers(self):
+        # Messages of a tag are numbered in the order they were sent
+        for i in range(NUM_MESSAGES):
+            msg = f"Numbered message {i}"
+            self.sender.isend(msg.encode(), tag=1, latency=None).wait()
+            recv, seq = self.receiver.irecv(tag=1).wait_with_seq()
+            assert (recv.decode(), seq) == (msg, i)
+
+    def test_probe(self):
+        # Probes report the size of the next message without receiving it
+        assert self.receiver.iprobe(tag=2) is None
+        self.sender.isend(b"probed", tag=2, latency=None).wait()
+        assert self.receiver.probe(tag=2) == 6
+        assert self.receiver.iprobe(tag=2) == 6
+        assert self.receiver.irecv(tag=2).wait() == b"probed"
+        assert self.receiver.iprobe(tag=2) is None
+
+    def test_any_tag(self):
+        # Messages are received from whichever tag they arrive on
+        for tag in [3, 4]:
+            work = self.receiver.irecv_any()
+            msg = f"Message via tag {tag}"
+            self.sender.isend(msg.encode(), tag=tag, latency=None).wait()
+            recv_tag, recv = work.wait()
+            assert (recv_tag, recv.decode()) == (tag, msg)
+
 def test_unidirectional_communication():
     test = UnidirectionalTest()
     
@@ -61,3 +87,10 @@ def test_unidirectional_communication():
     
     # Run async message test
     test.test_async_messages()
+
+    # Run sequence number test
+    test.test_sequence_numbers()
+
+    # Run probe and any-tag tests
+    test.test_probe()
+    test.test_any_tag()
@@ -0,0 +1,79 @@
+use hmac::{Hmac, Mac};
+use iroh::NodeId;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of the proof that a sender knows the cluster token
+pub const PROOF_LEN: usize = 32;
+
+fn proof_mac(token: &[u8], sender_id: &NodeId, receiver_id: &NodeId) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(token).expect("HMAC accepts keys of any length");
+    mac.update(sender_id.as_bytes());
+    mac.update(receiver_id.as_bytes());
+    mac
+}
+
+/// Prove knowledge of the cluster token to a receiver. The proof is bound to both node
+/// ids, so a receiver cannot replay it to authenticate as the sender elsewhere
+pub fn cluster_proof(token: &[u8], sender_id: &NodeId, receiver_id: &NodeId) -> Vec<u8> {
+    proof_mac(token, sender_id, receiver_id)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+/// Check a proof created by [`cluster_proof`] in constant time
+pub fn verify_cluster_proof(
+    token: &[u8],
+    sender_id: &NodeId,
+    receiver_id: &NodeId,
+    proof: &[u8],
+) -> bool {
+    proof_mac(token, sender_id, receiver_id)
+        .verify_slice(proof)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn node_id() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    #[test]
+    fn test_cluster_proof() {
+        let (sender_id, receiver_id) = (node_id(), node_id());
+        let proof = cluster_proof(b"token", &sender_id, &receiver_id);
+        assert_eq!(proof.len(), PROOF_LEN);
+        assert!(verify_cluster_proof(
+            b"token",
+            &sender_id,
+            &receiver_id,
+            &proof
+        ));
+
+        // Wrong token, swapped node ids or truncated proof
+        assert!(!verify_cluster_proof(
+            b"other",
+            &sender_id,
+            &receiver_id,
+            &proof
+        ));
+        assert!(!verify_cluster_proof(
+            b"token",
+            &receiver_id,
+            &sender_id,
+            &proof
+        ));
+        assert!(!verify_cluster_proof(
+            b"token",
+            &sender_id,
+            &receiver_id,
+            &proof[..16]
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+/*!
+ * Export of received tensors via the DLPack protocol, so that Python frameworks (e.g.
+ * `torch.from_dlpack` or `numpy.from_dlpack`) can wrap them without copying
+ */
+
+use anyhow::Result;
+use pyo3::exceptions::PyBufferError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use std::ffi::{CStr, c_void};
+
+use crate::tensor::{DType, Tensor};
+
+// Name of capsules holding a tensor that has not been consumed yet
+const DLTENSOR_NAME: &CStr = c"dltensor";
+
+// Device type of tensors in CPU memory
+const DL_CPU: i32 = 1;
+
+// Type codes of elements
+const DL_INT: u8 = 0;
+const DL_UINT: u8 = 1;
+const DL_FLOAT: u8 = 2;
+const DL_BFLOAT: u8 = 4;
+const DL_BOOL: u8 = 6;
+
+#[repr(C)]
+struct DLDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+struct DLDataType {
+    code: u8,
+    bits: u8,
+    lanes: u16,
+}
+
+impl From<DType> for DLDataType {
+    fn from(dtype: DType) -> Self {
+        let code = match dtype {
+            DType::Bool => DL_BOOL,
+            DType::U8 => DL_UINT,
+            DType::I8 | DType::I16 | DType::I32 | DType::I64 => DL_INT,
+            DType::F16 | DType::F32 | DType::F64 => DL_FLOAT,
+            DType::BF16 => DL_BFLOAT,
+        };
+        Self {
+            code,
+            bits: (dtype.size() * 8) as u8,
+            lanes: 1,
+        }
+    }
+}
+
+#[repr(C)]
+struct DLTensor {
+    data: *mut c_void,
+    device: DLDevice,
+    ndim: i32,
+    dtype: DLDataType,
+    shape: *mut i64,
+    strides: *mut i64,
+    byte_offset: u64,
+}
+
+#[repr(C)]
+struct DLManagedTensor {
+    dl_tensor: DLTensor,
+    manager_ctx: *mut c_void,
+    deleter: Option<unsafe extern "C" fn(*mut DLManagedTensor)>,
+}
+
+// Managed tensor together with the memory it points to, which is freed by its deleter
+#[repr(C)]
+struct ManagedTensor {
+    managed: DLManagedTensor,
+    tensor: Tensor,
+    shape: Vec<i64>,
+    strides: Vec<i64>,
+}
+
+impl ManagedTensor {
+    fn new(tensor: Tensor) -> Result<Box<Self>> {
+        // DLPack has strides in elements, and no notion of byte order
+        tensor.validate()?;
+        let tensor = tensor.into_native();
+        let shape = tensor
+            .shape
+            .iter()
+            .map(|&dim| i64::try_from(dim))
+            .collect::<Result<Vec<_>, _>>()?;
+        let strides = tensor
+            .strides
+            .iter()
+            .map(|&stride| i64::try_from(stride / tensor.dtype.size()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ndim = i32::try_from(shape.len())?;
+
+        let mut managed = Box::new(Self {
+            managed: DLManagedTensor {
+                dl_tensor: DLTensor {
+                    data: std::ptr::null_mut(),
+                    device: DLDevice {
+                        device_type: DL_CPU,
+                        device_id: 0,
+                    },
+                    ndim,
+                    dtype: tensor.dtype.into(),
+                    shape: std::ptr::null_mut(),
+                    strides: std::ptr::null_mut(),
+                    byte_offset: 0,
+                },
+                manager_ctx: std::ptr::null_mut(),
+                deleter: Some(Self::deleter),
+            },
+            tensor,
+            shape,
+            strides,
+        });
+
+        // Point into the memory owned by the box, which does not move anymore
+        let dl_tensor = &mut managed.managed.dl_tensor;
+        dl_tensor.data = managed.tensor.data.as_mut_ptr() as *mut c_void;
+        dl_tensor.shape = managed.shape.as_mut_ptr();
+        dl_tensor.strides = managed.strides.as_mut_ptr();
+        Ok(managed)
+    }
+
+    unsafe extern "C" fn deleter(managed: *mut DLManagedTensor) {
+        // SAFETY: The managed tensor is the first field of a boxed `ManagedTensor`
+        drop(unsafe { Box::from_raw(managed as *mut ManagedTensor) });
+    }
+}
+
+// Free tensors of capsules that were never consumed, consumers rename the capsule when
+// they take over the tensor (and call its deleter themselves)
+unsafe extern "C" fn capsule_destructor(capsule: *mut ffi::PyObject) {
+    unsafe {
+        if ffi::PyCapsule_IsValid(capsule, DLTENSOR_NAME.as_ptr()) == 1 {
+            let managed =
+                ffi::PyCapsule_GetPointer(capsule, DLTENSOR_NAME.as_ptr()) as *mut DLManagedTensor;
+            ManagedTensor::deleter(managed);
+        }
+    }
+}
+
+/// Received message or tensor that can be wrapped (once) via the DLPack protocol, e.g.
+/// with `torch.from_dlpack`, without copying its data
+#[pyclass]
+pub struct DLPackTensor {
+    tensor: Option<Tensor>,
+}
+
+impl DLPackTensor {
+    pub fn new(tensor: Tensor) -> Self {
+        Self {
+            tensor: Some(tensor),
+        }
+    }
+}
+
+#[pymethods]
+impl DLPackTensor {
+    #[pyo3(signature = (*, stream=None, max_version=None, dl_device=None, copy=None))]
+    pub fn __dlpack__(
+        &mut self,
+        py: Python<'_>,
+        stream: Option<PyObject>,
+        max_version: Option<(u32, u32)>,
+        dl_device: Option<(i32, i32)>,
+        copy: Option<bool>,
+    ) -> PyResult<PyObject> {
+        // Streams only apply to GPU tensors, and the legacy (unversioned) capsule is
+        // accepted by all consumers
+        let _ = (stream, max_version, copy);
+        if let Some(device) = dl_device
+            && device != (DL_CPU, 0)
+        {
+            return Err(PyBufferError::new_err(format!(
+                "Cannot export tensor to device {:?}, only the CPU is supported",
+                device
+            )));
+        }
+        let tensor = self
+            .tensor
+            .take()
+            .ok_or_else(|| PyBufferError::new_err("Tensor has already been exported"))?;
+        let managed = ManagedTensor::new(tensor)
+            .map_err(|e| PyBufferError::new_err(format!("Cannot export tensor: {}", e)))?;
+        let managed = Box::into_raw(managed);
+
+        // SAFETY: The capsule takes ownership of the managed tensor, which is freed
+        // either by its destructor or by the consumer
+        unsafe {
+            let capsule = ffi::PyCapsule_New(
+                managed as *mut c_void,
+                DLTENSOR_NAME.as_ptr(),
+                Some(capsule_destructor),
+            );
+            if capsule.is_null() {
+                ManagedTensor::deleter(managed as *mut DLManagedTensor);
+                return Err(PyErr::fetch(py));
+            }
+            Ok(PyObject::from_owned_ptr(py, capsule))
+        }
+    }
+
+    pub fn __dlpack_device__(&self) -> (i32, i32) {
+        (DL_CPU, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_managed_tensor() -> Result<()> {
+        let data: Vec<u8> = (0..24).collect();
+        let tensor = Tensor::new(DType::BF16, vec![3, 4], data)?;
+        let managed = Box::into_raw(ManagedTensor::new(tensor)?);
+
+        // SAFETY: The tensor is only freed once, by its deleter
+        unsafe {
+            let dl_tensor = &(*managed).managed.dl_tensor;
+            assert_eq!(dl_tensor.ndim, 2);
+            assert_eq!(
+                (dl_tensor.dtype.code, dl_tensor.dtype.bits),
+                (DL_BFLOAT, 16)
+            );
+            assert_eq!(std::slice::from_raw_parts(dl_tensor.shape, 2), [3, 4]);
+            assert_eq!(std::slice::from_raw_parts(dl_tensor.strides, 2), [4, 1]);
+            assert_eq!(*(dl_tensor.data as *const u8).add(23), 23);
+            let deleter = (*managed).managed.deleter.unwrap();
+            deleter(managed as *mut DLManagedTensor);
+        }
+        Ok(())
+    }
+}
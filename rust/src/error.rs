@@ -0,0 +1,201 @@
+use anyhow::Error;
+use iroh::{
+    NodeId,
+    endpoint::{Connection, ConnectionError, VarInt},
+};
+use std::fmt;
+
+use crate::receiver::{HANDSHAKE_CODE, REJECTED_CODE, UNAUTHORIZED_CODE};
+
+/// Error of works that were in flight when the connection to their peer was lost
+#[derive(Debug)]
+pub struct ConnectionReset {
+    pub peer_id: NodeId,
+    pub reason: ConnectionError,
+}
+
+impl fmt::Display for ConnectionReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Connection to peer {} reset: {}",
+            self.peer_id.fmt_short(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for ConnectionReset {}
+
+/// Error of messages that exceed the maximum message size of the receiver (or the
+/// address space of the receiving machine). Sends fail before writing anything, receives
+/// reset only the stream of the tag, later messages of the tag are received normally
+#[derive(Debug)]
+pub struct MessageTooLarge {
+    pub peer_id: NodeId,
+    pub tag: usize,
+    pub size: u64,
+    pub max_size: usize,
+}
+
+impl fmt::Display for MessageTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Message of {} bytes via stream {} of peer {} exceeds maximum size of {} bytes",
+            self.size,
+            self.tag,
+            self.peer_id.fmt_short(),
+            self.max_size
+        )
+    }
+}
+
+impl std::error::Error for MessageTooLarge {}
+
+/// Error of receives into a buffer whose size differs from the size of the incoming
+/// message. The message is dropped, and later messages of the tag can still be received
+#[derive(Debug)]
+pub struct MessageSizeMismatch {
+    pub peer_id: NodeId,
+    pub tag: usize,
+    pub size: usize,
+    pub buffer_size: usize,
+}
+
+impl fmt::Display for MessageSizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Message of {} bytes via stream {} of peer {} does not match buffer of {} bytes",
+            self.size,
+            self.tag,
+            self.peer_id.fmt_short(),
+            self.buffer_size
+        )
+    }
+}
+
+impl std::error::Error for MessageSizeMismatch {}
+
+/// Error of receives whose message does not match the checksum in its trailer, e.g.
+/// because of a framing bug. The message is dropped, later messages of the tag are
+/// received normally
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub peer_id: NodeId,
+    pub tag: usize,
+    /// Sequence number of the message among the messages of the tag
+    pub seq: u64,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Message {} via stream {} of peer {} does not match its checksum",
+            self.seq,
+            self.tag,
+            self.peer_id.fmt_short()
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// Error of receives whose message has a sequence number that was already received via
+/// its tag, i.e. a duplicate or a message that arrived out of order. The message is
+/// dropped, later messages of the tag are received normally
+#[derive(Debug)]
+pub struct DuplicateMessage {
+    pub peer_id: NodeId,
+    pub tag: usize,
+    pub seq: u64,
+    /// Sequence number of the next message expected via the tag
+    pub expected: u64,
+}
+
+impl fmt::Display for DuplicateMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Message {} via stream {} of peer {} was already received (expected message {})",
+            self.seq,
+            self.tag,
+            self.peer_id.fmt_short(),
+            self.expected
+        )
+    }
+}
+
+impl std::error::Error for DuplicateMessage {}
+
+/// Error of sends on a stream that the peer reset, e.g. because a message exceeded its
+/// maximum message size. The next message of the tag is sent on a new stream
+#[derive(Debug)]
+pub struct StreamStopped {
+    pub peer_id: NodeId,
+    pub tag: usize,
+    pub code: VarInt,
+}
+
+impl fmt::Display for StreamStopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stream {} to peer {} stopped by peer (code {})",
+            self.tag,
+            self.peer_id.fmt_short(),
+            self.code
+        )
+    }
+}
+
+impl std::error::Error for StreamStopped {}
+
+/// Replace errors caused by a lost connection with a [`ConnectionReset`]
+pub(crate) fn map_connection_reset(connection: &Connection, peer_id: NodeId, e: Error) -> Error {
+    match connection.close_reason() {
+        Some(reason) => ConnectionReset { peer_id, reason }.into(),
+        None => e,
+    }
+}
+
+/// Error of connection attempts that the peer refused, e.g. because of a mismatched
+/// configuration. Retrying such attempts does not help
+#[derive(Debug)]
+pub struct ConnectionRejected {
+    pub peer_id: NodeId,
+    pub reason: String,
+}
+
+impl fmt::Display for ConnectionRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Connection to peer {} rejected: {}",
+            self.peer_id.fmt_short(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for ConnectionRejected {}
+
+/// Replace errors caused by the peer refusing a connection with a [`ConnectionRejected`]
+pub(crate) fn map_connection_rejected(connection: &Connection, peer_id: NodeId, e: Error) -> Error {
+    match connection.close_reason() {
+        Some(ConnectionError::ApplicationClosed(close))
+            if [REJECTED_CODE, UNAUTHORIZED_CODE, HANDSHAKE_CODE]
+                .iter()
+                .any(|&code| close.error_code == code.into()) =>
+        {
+            ConnectionRejected {
+                peer_id,
+                reason: String::from_utf8_lossy(&close.reason).into_owned(),
+            }
+            .into()
+        }
+        _ => e,
+    }
+}
@@ -0,0 +1,381 @@
+use anyhow::{Result, anyhow, bail, ensure};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::precision::Precision;
+
+/// Length of a frame header
+pub const FRAME_HEADER_LEN: usize = 35;
+
+/// Messages below this size are sent uncompressed by default, since compressing them
+/// costs more time than sending them
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Compression codec of a message
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Codec {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    fn code(&self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Zstd),
+            2 => Ok(Codec::Lz4),
+            _ => bail!("Unknown compression codec {}", code),
+        }
+    }
+
+    /// Compress a message, returning `None` if the codec does not compress at all
+    pub fn compress(&self, msg: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(match self {
+            Codec::None => None,
+            Codec::Zstd => Some(zstd::bulk::compress(msg, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            Codec::Lz4 => Some(lz4_flex::block::compress(msg)),
+        })
+    }
+
+    /// Decompress a message into a buffer of exactly its uncompressed size
+    pub fn decompress_into(&self, compressed: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let size = match self {
+            Codec::None => {
+                ensure!(
+                    compressed.len() == buffer.len(),
+                    "Uncompressed message does not match its size"
+                );
+                buffer.copy_from_slice(compressed);
+                buffer.len()
+            }
+            Codec::Zstd => zstd::bulk::decompress_to_buffer(compressed, buffer)?,
+            Codec::Lz4 => lz4_flex::block::decompress_into(compressed, buffer)?,
+        };
+        ensure!(
+            size == buffer.len(),
+            "Message decompressed to {} bytes, expected {} bytes",
+            size,
+            buffer.len()
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+        })
+    }
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Codec::None),
+            "zstd" => Ok(Codec::Zstd),
+            "lz4" => Ok(Codec::Lz4),
+            _ => Err(anyhow!(
+                "Invalid compression codec: {}, expected 'none', 'zstd' or 'lz4'",
+                s
+            )),
+        }
+    }
+}
+
+/// Checksum of a message, sent as a trailer after its body (i.e. the message as written
+/// to the stream, after compression)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    #[default]
+    None,
+    Crc32c,
+    Xxh3,
+}
+
+impl Checksum {
+    fn code(&self) -> u8 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32c => 1,
+            Checksum::Xxh3 => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(Checksum::None),
+            1 => Ok(Checksum::Crc32c),
+            2 => Ok(Checksum::Xxh3),
+            _ => bail!("Unknown checksum {}", code),
+        }
+    }
+
+    /// Length of the trailer in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32c => 4,
+            Checksum::Xxh3 => 8,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Trailer of a message, i.e. its checksum (little-endian)
+    pub fn trailer(&self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            Checksum::None => Vec::new(),
+            Checksum::Crc32c => crc32c::crc32c(msg).to_le_bytes().to_vec(),
+            Checksum::Xxh3 => xxhash_rust::xxh3::xxh3_64(msg).to_le_bytes().to_vec(),
+        }
+    }
+
+    /// Check a message against its trailer
+    pub fn verify(&self, msg: &[u8], trailer: &[u8]) -> bool {
+        self.trailer(msg) == trailer
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Checksum::None => "none",
+            Checksum::Crc32c => "crc32c",
+            Checksum::Xxh3 => "xxh3",
+        })
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Checksum::None),
+            "crc32c" => Ok(Checksum::Crc32c),
+            "xxh3" => Ok(Checksum::Xxh3),
+            _ => Err(anyhow!(
+                "Invalid checksum: {}, expected 'none', 'crc32c' or 'xxh3'",
+                s
+            )),
+        }
+    }
+}
+
+/// Compression of sent messages, with a codec for the whole node that can be overridden
+/// per tag. Receivers decompress messages transparently, whatever their codec
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Compression {
+    /// Codec of tags without a codec of their own
+    pub codec: Codec,
+    /// Codecs of individual tags
+    pub tags: HashMap<usize, Codec>,
+    /// Messages smaller than this many bytes are sent uncompressed
+    pub threshold: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            codec: Codec::None,
+            tags: HashMap::new(),
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl Compression {
+    /// Codec of a message of the given size sent via a tag
+    pub fn codec(&self, tag: usize, size: usize) -> Codec {
+        if size < self.threshold {
+            return Codec::None;
+        }
+        self.tags.get(&tag).copied().unwrap_or(self.codec)
+    }
+}
+
+/// Header preceding every message on the stream of its tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Size of the message on the wire (after compression)
+    pub size: u64,
+    pub codec: Codec,
+    /// Precision of a downcast float32 tensor, which the receiver restores after
+    /// decompressing the message
+    pub precision: Precision,
+    /// Checksum in the trailer of the message
+    pub checksum: Checksum,
+    /// Size of the message after decompression
+    pub raw_size: u64,
+    /// Size of the message once received, i.e. of the restored float32 tensor for
+    /// downcast tensors and the size after decompression otherwise
+    pub restored_size: u64,
+    /// Sequence number of the message among the messages of its tag
+    pub seq: u64,
+}
+
+impl FrameHeader {
+    /// Header of an uncompressed message
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            codec: Codec::None,
+            precision: Precision::Full,
+            checksum: Checksum::None,
+            raw_size: size,
+            restored_size: size,
+            seq: 0,
+        }
+    }
+
+    /// Serialize the header as size (u64), codec (u8), precision (u8), checksum (u8),
+    /// uncompressed size (u64), restored size (u64) and sequence number (u64, all
+    /// integers are little-endian)
+    pub fn encode(&self) -> [u8; FRAME_HEADER_LEN] {
+        let mut header = [0; FRAME_HEADER_LEN];
+        header[..8].copy_from_slice(&self.size.to_le_bytes());
+        header[8] = self.codec.code();
+        header[9] = self.precision.code();
+        header[10] = self.checksum.code();
+        header[11..19].copy_from_slice(&self.raw_size.to_le_bytes());
+        header[19..27].copy_from_slice(&self.restored_size.to_le_bytes());
+        header[27..].copy_from_slice(&self.seq.to_le_bytes());
+        header
+    }
+
+    pub fn decode(header: &[u8; FRAME_HEADER_LEN]) -> Result<Self> {
+        let header = Self {
+            size: u64::from_le_bytes(header[..8].try_into().unwrap()),
+            codec: Codec::from_code(header[8])?,
+            precision: Precision::from_code(header[9])?,
+            checksum: Checksum::from_code(header[10])?,
+            raw_size: u64::from_le_bytes(header[11..19].try_into().unwrap()),
+            restored_size: u64::from_le_bytes(header[19..27].try_into().unwrap()),
+            seq: u64::from_le_bytes(header[27..].try_into().unwrap()),
+        };
+        ensure!(
+            header.codec != Codec::None || header.size == header.raw_size,
+            "Uncompressed message of {} bytes claims a size of {} bytes",
+            header.size,
+            header.raw_size
+        );
+        ensure!(
+            header.precision != Precision::Full || header.raw_size == header.restored_size,
+            "Message of {} bytes at full precision claims a restored size of {} bytes",
+            header.raw_size,
+            header.restored_size
+        );
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_header_roundtrip() -> Result<()> {
+        let header = FrameHeader {
+            size: 42,
+            codec: Codec::Zstd,
+            precision: Precision::FP8,
+            checksum: Checksum::Crc32c,
+            raw_size: 1 << 40,
+            restored_size: 1 << 41,
+            seq: u64::MAX,
+        };
+        assert_eq!(FrameHeader::decode(&header.encode())?, header);
+        assert_eq!(
+            FrameHeader::decode(&FrameHeader::new(7).encode())?.raw_size,
+            7
+        );
+
+        // Unknown codecs, precisions and checksums, and inconsistent sizes are rejected
+        for field in 8..11 {
+            let mut invalid = header.encode();
+            invalid[field] = 255;
+            assert!(FrameHeader::decode(&invalid).is_err());
+        }
+        let mut invalid = FrameHeader::new(7).encode();
+        invalid[11] = 8;
+        assert!(FrameHeader::decode(&invalid).is_err());
+        let mut invalid = FrameHeader::new(7).encode();
+        invalid[19] = 8;
+        assert!(FrameHeader::decode(&invalid).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_codecs() -> Result<()> {
+        let msg: Vec<u8> = (0..16384).map(|i| (i % 7) as u8).collect();
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            let compressed = codec.compress(&msg)?.unwrap();
+            assert!(compressed.len() < msg.len());
+            let mut buffer = vec![0; msg.len()];
+            codec.decompress_into(&compressed, &mut buffer)?;
+            assert_eq!(buffer, msg);
+
+            // Buffers of the wrong size are not filled silently
+            let mut buffer = vec![0; msg.len() + 1];
+            assert!(codec.decompress_into(&compressed, &mut buffer).is_err());
+            assert_eq!(codec.to_string().parse::<Codec>()?, codec);
+        }
+        assert!(Codec::None.compress(&msg)?.is_none());
+        assert!("gzip".parse::<Codec>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksums() -> Result<()> {
+        for checksum in [Checksum::None, Checksum::Crc32c, Checksum::Xxh3] {
+            let trailer = checksum.trailer(b"message");
+            assert_eq!(trailer.len(), checksum.len());
+            assert!(checksum.verify(b"message", &trailer));
+            assert_eq!(checksum.to_string().parse::<Checksum>()?, checksum);
+        }
+
+        // Known CRC32C, and any corruption is detected
+        assert_eq!(
+            Checksum::Crc32c.trailer(b"123456789"),
+            0xe3069283u32.to_le_bytes()
+        );
+        for checksum in [Checksum::Crc32c, Checksum::Xxh3] {
+            let trailer = checksum.trailer(b"message");
+            assert!(!checksum.verify(b"messagf", &trailer));
+        }
+        assert!("md5".parse::<Checksum>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_codec() {
+        let compression = Compression {
+            codec: Codec::Zstd,
+            tags: HashMap::from([(1, Codec::Lz4), (2, Codec::None)]),
+            threshold: 1024,
+        };
+        assert_eq!(compression.codec(0, 4096), Codec::Zstd);
+        assert_eq!(compression.codec(1, 4096), Codec::Lz4);
+        assert_eq!(compression.codec(2, 4096), Codec::None);
+        assert_eq!(compression.codec(0, 100), Codec::None);
+        assert_eq!(Compression::default().codec(0, 1 << 20), Codec::None);
+    }
+}
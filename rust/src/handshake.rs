@@ -0,0 +1,295 @@
+use anyhow::{Result, bail, ensure};
+use iroh::endpoint::{RecvStream, SendStream, TransportConfig, VarInt};
+
+use crate::auth::PROOF_LEN;
+
+/// Magic bytes opening every stream, to tell prime-iroh peers apart from anything else
+pub const MAGIC: [u8; 4] = *b"PIRH";
+
+/// Version of the wire protocol, to be bumped on every incompatible change
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Length of the fixed-size part of a handshake frame
+const HEADER_LEN: usize = 28;
+
+/// Encoding of an unlimited maximum message size
+const UNLIMITED: u64 = u64::MAX;
+
+/// Maximum number of streams of a connection (those of the handshake included), i.e. of
+/// tags that a sender can use with a peer. Receivers grant their peers exactly this many
+/// concurrent streams
+pub const MAX_STREAMS: usize = 1024;
+
+/// Transport configuration of endpoints, granting peers [`MAX_STREAMS`] streams
+pub fn transport_config() -> TransportConfig {
+    let mut config = TransportConfig::default();
+    config.max_concurrent_uni_streams(VarInt::from_u32(MAX_STREAMS as u32));
+    config
+}
+
+/// Magic bytes opening streams of tags that are opened lazily, after the handshake
+pub const TAG_MAGIC: [u8; 4] = *b"PIRT";
+
+/// Length of a tag header
+const TAG_HEADER_LEN: usize = 12;
+
+/// Frame written by the sender at the start of every stream, and by the receiver to
+/// acknowledge that all streams of a connection were accepted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: u32,
+    pub stream_index: u32,
+    pub num_streams: u32,
+    /// Maximum size of messages the writer of the frame accepts, if limited
+    pub max_message_size: Option<u64>,
+    /// Proof of knowledge of the cluster token, empty if there is none
+    pub proof: Vec<u8>,
+}
+
+impl Handshake {
+    pub fn new(stream_index: usize, num_streams: usize, proof: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            version: PROTOCOL_VERSION,
+            stream_index: stream_index.try_into()?,
+            num_streams: num_streams.try_into()?,
+            max_message_size: None,
+            proof,
+        })
+    }
+
+    /// Serialize the frame as magic, version, stream index, number of streams, maximum
+    /// message size (u64) and length-prefixed proof (all integers are little-endian and
+    /// u32 unless noted otherwise)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEADER_LEN + self.proof.len());
+        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&self.version.to_le_bytes());
+        frame.extend_from_slice(&self.stream_index.to_le_bytes());
+        frame.extend_from_slice(&self.num_streams.to_le_bytes());
+        frame.extend_from_slice(&self.max_message_size.unwrap_or(UNLIMITED).to_le_bytes());
+        frame.extend_from_slice(&(self.proof.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&self.proof);
+        frame
+    }
+
+    /// Parse the fixed-size part of a frame, returning the frame without its proof and
+    /// the length of the proof that follows
+    fn decode_header(header: &[u8; HEADER_LEN]) -> Result<(Self, usize)> {
+        let field = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        if header[..4] != MAGIC {
+            bail!("Not a prime-iroh handshake (incompatible library version?)");
+        }
+        let version = field(4);
+        ensure!(
+            version == PROTOCOL_VERSION,
+            "Protocol version mismatch (got {}, expected {})",
+            version,
+            PROTOCOL_VERSION
+        );
+        let max_message_size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let proof_len = field(24) as usize;
+        ensure!(
+            proof_len == 0 || proof_len == PROOF_LEN,
+            "Invalid cluster token proof of {} bytes",
+            proof_len
+        );
+        let handshake = Self {
+            version,
+            stream_index: field(8),
+            num_streams: field(12),
+            max_message_size: (max_message_size != UNLIMITED).then_some(max_message_size),
+            proof: Vec::new(),
+        };
+        Ok((handshake, proof_len))
+    }
+
+    pub async fn write(&self, stream: &mut SendStream) -> Result<()> {
+        stream.write_all(&self.encode()).await?;
+        Ok(())
+    }
+
+    pub async fn read(stream: &mut RecvStream) -> Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        stream.read_exact(&mut header).await?;
+        let (mut handshake, proof_len) = Self::decode_header(&header)?;
+        handshake.proof = vec![0; proof_len];
+        stream.read_exact(&mut handshake.proof).await?;
+        Ok(handshake)
+    }
+
+    /// Number of streams to accept for a connection opened by this frame, which is the
+    /// sender's number of streams as long as it does not exceed `max_streams`
+    pub fn negotiate(&self, max_streams: usize) -> Result<usize> {
+        ensure!(
+            self.stream_index == 0,
+            "Unexpected stream {} (expected stream 0)",
+            self.stream_index
+        );
+        let num_streams = self.num_streams as usize;
+        ensure!(num_streams > 0, "Sender uses no streams");
+        ensure!(
+            num_streams <= max_streams,
+            "Sender uses {} streams, receiver accepts at most {}",
+            num_streams,
+            max_streams
+        );
+        Ok(num_streams)
+    }
+
+    /// Check that the frame opens the expected stream of a connection with the
+    /// negotiated number of streams
+    pub fn validate(&self, stream_index: usize, num_streams: usize) -> Result<()> {
+        ensure!(
+            self.num_streams as usize == num_streams,
+            "Number of streams mismatch (sender uses {}, receiver uses {})",
+            self.num_streams,
+            num_streams
+        );
+        ensure!(
+            self.stream_index as usize == stream_index,
+            "Unexpected stream {} (expected stream {})",
+            self.stream_index,
+            stream_index
+        );
+        Ok(())
+    }
+}
+
+/// Header announcing the tag of a stream that the sender opened on first use of the tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagHeader {
+    pub tag: u64,
+}
+
+impl TagHeader {
+    pub fn new(tag: usize) -> Result<Self> {
+        Ok(Self {
+            tag: tag.try_into()?,
+        })
+    }
+
+    /// Serialize the header as magic and tag (a little-endian u64)
+    pub fn encode(&self) -> [u8; TAG_HEADER_LEN] {
+        let mut header = [0; TAG_HEADER_LEN];
+        header[..4].copy_from_slice(&TAG_MAGIC);
+        header[4..].copy_from_slice(&self.tag.to_le_bytes());
+        header
+    }
+
+    fn decode(header: &[u8; TAG_HEADER_LEN]) -> Result<Self> {
+        ensure!(
+            header[..4] == TAG_MAGIC,
+            "Stream does not start with a tag header"
+        );
+        Ok(Self {
+            tag: u64::from_le_bytes(header[4..].try_into().unwrap()),
+        })
+    }
+
+    pub async fn read(stream: &mut RecvStream) -> Result<Self> {
+        let mut header = [0; TAG_HEADER_LEN];
+        stream.read_exact(&mut header).await?;
+        Self::decode(&header)
+    }
+
+    /// Tag as used by this node, which fails for tags beyond the range of `usize`
+    pub fn tag(&self) -> Result<usize> {
+        Ok(self.tag.try_into()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(frame: &[u8]) -> Result<Handshake> {
+        let (mut handshake, proof_len) = Handshake::decode_header(frame[..HEADER_LEN].try_into()?)?;
+        ensure!(
+            frame.len() == HEADER_LEN + proof_len,
+            "Invalid frame length"
+        );
+        handshake.proof = frame[HEADER_LEN..].to_vec();
+        Ok(handshake)
+    }
+
+    #[test]
+    fn test_handshake_roundtrip() -> Result<()> {
+        let handshake = Handshake::new(1, 2, vec![]).unwrap();
+        assert_eq!(decode(&handshake.encode())?, handshake);
+
+        let handshake = Handshake::new(0, 1, vec![7; PROOF_LEN]).unwrap();
+        assert_eq!(decode(&handshake.encode())?, handshake);
+
+        let handshake = Handshake {
+            max_message_size: Some(1024),
+            ..Handshake::new(0, 1, vec![]).unwrap()
+        };
+        assert_eq!(decode(&handshake.encode())?, handshake);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_rejects_invalid_frames() {
+        // Dummy preamble of older versions
+        assert!(decode(&[0; HEADER_LEN]).is_err());
+
+        // Other protocol version
+        let mut handshake = Handshake::new(0, 1, vec![]).unwrap();
+        handshake.version = PROTOCOL_VERSION + 1;
+        let err = decode(&handshake.encode()).unwrap_err();
+        assert!(err.to_string().contains("Protocol version"));
+
+        // Oversized proof
+        let frame = Handshake::new(0, 1, vec![0; PROOF_LEN + 1])
+            .unwrap()
+            .encode();
+        assert!(decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_handshake_validate() {
+        let handshake = Handshake::new(1, 2, vec![]).unwrap();
+        assert!(handshake.validate(1, 2).is_ok());
+        assert!(handshake.validate(0, 2).is_err());
+        let err = handshake.validate(1, 4).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Number of streams mismatch (sender uses 2, receiver uses 4)"
+        );
+    }
+
+    #[test]
+    fn test_handshake_negotiate() {
+        assert_eq!(
+            Handshake::new(0, 2, vec![]).unwrap().negotiate(4).unwrap(),
+            2
+        );
+        assert_eq!(
+            Handshake::new(0, 4, vec![]).unwrap().negotiate(4).unwrap(),
+            4
+        );
+        let err = Handshake::new(0, 8, vec![])
+            .unwrap()
+            .negotiate(4)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sender uses 8 streams, receiver accepts at most 4"
+        );
+        assert!(Handshake::new(0, 0, vec![]).unwrap().negotiate(4).is_err());
+        assert!(Handshake::new(1, 2, vec![]).unwrap().negotiate(4).is_err());
+    }
+
+    #[test]
+    fn test_tag_header_roundtrip() -> Result<()> {
+        let header = TagHeader::new(1234)?;
+        assert_eq!(TagHeader::decode(&header.encode())?.tag()?, 1234);
+
+        // Handshake frames are not tag headers
+        let frame = Handshake::new(0, 1, vec![])?.encode();
+        assert!(TagHeader::decode(frame[..TAG_HEADER_LEN].try_into()?).is_err());
+
+        Ok(())
+    }
+}
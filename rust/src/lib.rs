@@ -6,20 +6,272 @@
  */
 
 // Modules
+pub mod auth;
+mod dlpack;
+pub mod error;
+pub mod frame;
+pub mod handshake;
 pub mod node;
+pub mod precision;
+pub mod queue;
 pub mod receiver;
+pub mod retry;
 pub mod sender;
+pub mod tensor;
+#[cfg(test)]
+mod testing;
 pub mod work;
-use crate::node::Node as IrohNode;
-use crate::work::{RecvWork as IrohRecvWork, SendWork as IrohSendWork};
+use crate::dlpack::DLPackTensor;
+use crate::frame::{Checksum, Codec, Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::node::{Node as IrohNode, NodeConfig as IrohNodeConfig, parse_node_addr};
+use crate::precision::{Precision, TensorPrecision};
+use crate::retry::RetryPolicy as IrohRetryPolicy;
+use crate::tensor::{ByteOrder, DType, Tensor as IrohTensor};
+use crate::work::{
+    ConnectWork as IrohConnectWork, RecvWork as IrohRecvWork, SendWork as IrohSendWork,
+};
 
 // Miscellaneous
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::mem::ManuallyDrop;
+use std::str::FromStr;
 use std::sync::RwLock;
+use std::time::Duration;
 
 // Bindings
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
 use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyByteArray, PyDict, PyMemoryView};
+
+// Whether a Python object is a torch tensor
+fn is_torch(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(obj.get_type().module()?.to_str()?.starts_with("torch"))
+}
+
+// Get the memory of a Python object supporting the buffer protocol (e.g. bytes,
+// bytearray, memoryview or numpy arrays) as bytes, without copying it
+fn byte_buffer(obj: &Bound<'_, PyAny>) -> PyResult<PyBuffer<u8>> {
+    let view = match PyMemoryView::from(obj) {
+        Ok(view) => view,
+        // Tensors don't support the buffer protocol, but share their memory with the
+        // numpy view of their bytes, which also works for dtypes that numpy does not
+        // support (like bfloat16) and tensors that require grad. It fails for tensors that
+        // are not on the CPU
+        Err(_) if is_torch(obj)? => {
+            if !obj.call_method0("is_contiguous")?.extract::<bool>()? {
+                return Err(PyTypeError::new_err(
+                    "Cannot use a non-contiguous tensor as a buffer",
+                ));
+            }
+            let bytes = obj
+                .call_method0("detach")?
+                .call_method1("reshape", (-1,))?
+                .call_method1("view", (obj.py().import("torch")?.getattr("uint8")?,))?
+                .call_method0("numpy")?;
+            PyMemoryView::from(&bytes)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    // View any (C-contiguous) buffer as bytes, regardless of its item type
+    let view = view.call_method1("cast", ("B",))?;
+    PyBuffer::get(&view)
+}
+
+// Release a Python buffer, which takes the GIL. On a runtime thread (e.g. in the task
+// that sent from or failed to receive into the buffer), it is released on a blocking
+// thread instead, so that waiting for the GIL does not stall other tasks
+fn release_buffer(buffer: PyBuffer<u8>) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => drop(runtime.spawn_blocking(move || drop(buffer))),
+        Err(_) => drop(buffer),
+    }
+}
+
+/// Message sent directly from the memory of a Python object, without copying it
+struct PyMessage {
+    buffer: ManuallyDrop<PyBuffer<u8>>,
+}
+
+impl PyMessage {
+    fn new(msg: &Bound<'_, PyAny>) -> PyResult<Self> {
+        Ok(Self {
+            buffer: ManuallyDrop::new(byte_buffer(msg)?),
+        })
+    }
+}
+
+impl AsRef<[u8]> for PyMessage {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: The buffer is a C-contiguous byte buffer, which stays valid as long as
+        // it is held
+        unsafe {
+            std::slice::from_raw_parts(self.buffer.buf_ptr() as *const u8, self.buffer.len_bytes())
+        }
+    }
+}
+
+impl Drop for PyMessage {
+    fn drop(&mut self) {
+        // SAFETY: The buffer is not used after this
+        release_buffer(unsafe { ManuallyDrop::take(&mut self.buffer) });
+    }
+}
+
+/// Buffer of a writable Python object (e.g. a bytearray, numpy array or CPU tensor)
+/// that a message is received into, without copying it
+struct PyRecvBuffer {
+    buffer: ManuallyDrop<PyBuffer<u8>>,
+}
+
+impl PyRecvBuffer {
+    fn new(buffer: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let buffer = byte_buffer(buffer)?;
+        if buffer.readonly() {
+            return Err(PyTypeError::new_err(
+                "Cannot receive into a read-only buffer",
+            ));
+        }
+        Ok(Self {
+            buffer: ManuallyDrop::new(buffer),
+        })
+    }
+}
+
+impl Drop for PyRecvBuffer {
+    fn drop(&mut self) {
+        // SAFETY: The buffer is not used after this
+        release_buffer(unsafe { ManuallyDrop::take(&mut self.buffer) });
+    }
+}
+
+impl AsMut<[u8]> for PyRecvBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The buffer is a writable C-contiguous byte buffer, which stays valid as
+        // long as it is held
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.buffer.buf_ptr() as *mut u8,
+                self.buffer.len_bytes(),
+            )
+        }
+    }
+}
+
+// Convert a numpy array or torch tensor into a tensor message, copying its data
+fn tensor_from_py(py: Python<'_>, tensor: &Bound<'_, PyAny>) -> PyResult<IrohTensor> {
+    let to_py_err = |e: anyhow::Error| PyRuntimeError::new_err(e.to_string());
+    let shape: Vec<usize> = tensor.getattr("shape")?.extract()?;
+    let (dtype, byte_order, data) = if is_torch(tensor)? {
+        // View the (contiguous, CPU) data as bytes, which also works for dtypes that
+        // numpy does not support, like bfloat16
+        let dtype = tensor.getattr("dtype")?.str()?.to_string();
+        let data = tensor
+            .call_method0("detach")?
+            .call_method0("cpu")?
+            .call_method0("contiguous")?
+            .call_method1("reshape", (-1,))?
+            .call_method1("view", (py.import("torch")?.getattr("uint8")?,))?;
+        let dtype = dtype.strip_prefix("torch.").unwrap_or(&dtype).to_string();
+        (dtype, ByteOrder::native(), data)
+    } else {
+        let array = py
+            .import("numpy")?
+            .call_method1("ascontiguousarray", (tensor,))?;
+        let dtype = array.getattr("dtype")?;
+        let byte_order = match dtype.getattr("byteorder")?.extract::<String>()?.as_str() {
+            ">" => ByteOrder::Big,
+            "<" => ByteOrder::Little,
+            _ => ByteOrder::native(),
+        };
+        // View the data as bytes, since memoryviews only support native byte order
+        let data = array
+            .call_method1("reshape", (-1,))?
+            .call_method1("view", ("uint8",))?;
+        (dtype.getattr("name")?.extract()?, byte_order, data)
+    };
+    let dtype = DType::from_str(&dtype).map_err(to_py_err)?;
+    let data = byte_buffer(&data)?.to_vec(py)?;
+    let tensor = IrohTensor::new(dtype, shape, data).map_err(to_py_err)?;
+    Ok(IrohTensor {
+        byte_order,
+        ..tensor
+    })
+}
+
+// Convert a dict of numpy arrays or torch tensors into named tensor messages
+fn tensors_from_py(py: Python<'_>, tensors: &Bound<'_, PyDict>) -> PyResult<Vec<IrohTensor>> {
+    tensors
+        .iter()
+        .map(|(name, tensor)| Ok(tensor_from_py(py, &tensor)?.with_name(name.extract::<String>()?)))
+        .collect()
+}
+
+/// Type of the arrays that received tensors are converted into
+#[derive(Clone, Copy)]
+enum TensorFormat {
+    Numpy,
+    Torch,
+    DLPack,
+}
+
+impl FromStr for TensorFormat {
+    type Err = PyErr;
+
+    fn from_str(s: &str) -> PyResult<Self> {
+        match s {
+            "numpy" => Ok(TensorFormat::Numpy),
+            "torch" => Ok(TensorFormat::Torch),
+            "dlpack" => Ok(TensorFormat::DLPack),
+            _ => Err(PyRuntimeError::new_err(format!(
+                "Invalid tensor format: {}, expected 'numpy', 'torch' or 'dlpack'",
+                s
+            ))),
+        }
+    }
+}
+
+impl TensorFormat {
+    // Convert a received tensor into a numpy array or torch tensor (copying its data), or
+    // export it via DLPack
+    fn to_py(self, py: Python<'_>, tensor: IrohTensor) -> PyResult<PyObject> {
+        let tensor = tensor.into_native();
+        let (module, dtype) = match self {
+            TensorFormat::DLPack => {
+                return Ok(Py::new(py, DLPackTensor::new(tensor))?.into_any());
+            }
+            TensorFormat::Numpy if tensor.dtype == DType::BF16 => {
+                return Err(PyRuntimeError::new_err(
+                    "numpy has no bfloat16 dtype, receive bfloat16 tensors with format=\"torch\" \
+                     or format=\"dlpack\"",
+                ));
+            }
+            TensorFormat::Numpy => (
+                py.import("numpy")?,
+                tensor.dtype.name().into_pyobject(py)?.into_any(),
+            ),
+            TensorFormat::Torch => {
+                let torch = py.import("torch")?;
+                let dtype = torch.getattr(tensor.dtype.name())?;
+                (torch, dtype)
+            }
+        };
+        let kwargs = [("dtype", dtype)].into_py_dict(py)?;
+
+        // Empty buffers cannot be wrapped (by torch)
+        let array = if tensor.data.is_empty() {
+            module.call_method("empty", (tensor.shape,), Some(&kwargs))?
+        } else {
+            let data = PyByteArray::new(py, &tensor.data);
+            module
+                .call_method("frombuffer", (data,), Some(&kwargs))?
+                .call_method1("reshape", (tensor.shape,))?
+        };
+        Ok(array.unbind())
+    }
+}
 
 #[pyclass]
 pub struct SendWork {
@@ -37,16 +289,15 @@ impl SendWork {
 #[pymethods]
 impl SendWork {
     /// Wait for the work to complete and return the result
-    pub fn wait(&self) -> PyResult<()> {
+    pub fn wait(&self, py: Python<'_>) -> PyResult<()> {
         // Take the inner value out of the RwLock, leaving None in its place
         let mut write_guard = self
             .inner
             .write()
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         if let Some(inner) = write_guard.take() {
-            inner
-                .unwrap()
-                .wait()
+            // Release the GIL, which is needed to release the buffer of the message
+            py.allow_threads(|| inner.and_then(|work| work.wait()))
                 .map_err(|e| PyRuntimeError::new_err(e.to_string()))
         } else {
             Err(PyRuntimeError::new_err(
@@ -56,6 +307,53 @@ impl SendWork {
     }
 }
 
+#[pyclass]
+pub struct ConnectWork {
+    inner: RwLock<Option<Result<IrohConnectWork>>>,
+}
+
+impl ConnectWork {
+    pub fn new(inner: Result<IrohConnectWork>) -> Self {
+        Self {
+            inner: RwLock::new(Some(inner)),
+        }
+    }
+}
+
+#[pymethods]
+impl ConnectWork {
+    /// Check whether the work has completed, without blocking
+    pub fn is_completed(&self) -> PyResult<bool> {
+        let read_guard = self
+            .inner
+            .read()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(match read_guard.as_ref() {
+            Some(Ok(inner)) => inner.is_completed(),
+            // Failed to start or already consumed
+            Some(Err(_)) | None => true,
+        })
+    }
+
+    /// Wait for the work to complete and return the result
+    pub fn wait(&self, py: Python<'_>) -> PyResult<()> {
+        // Take the inner value out of the RwLock, leaving None in its place
+        let mut write_guard = self
+            .inner
+            .write()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(inner) = write_guard.take() {
+            // Release the GIL, so that other Python threads can run while connecting
+            py.allow_threads(|| inner.and_then(|work| work.wait()))
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "ConnectWork has already been consumed",
+            ))
+        }
+    }
+}
+
 #[pyclass]
 pub struct RecvWork {
     inner: RwLock<Option<Result<IrohRecvWork>>>,
@@ -68,29 +366,337 @@ impl RecvWork {
             inner: RwLock::new(Some(inner)),
         }
     }
+
+    fn take(&self) -> PyResult<IrohRecvWork> {
+        // Take the inner value out of the RwLock, leaving None in its place
+        let mut write_guard = self
+            .inner
+            .write()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(inner) = write_guard.take() {
+            inner.map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "RecvWork has already been consumed",
+            ))
+        }
+    }
 }
 
 #[pymethods]
 impl RecvWork {
-    pub fn wait(&self) -> PyResult<Vec<u8>> {
+    pub fn wait(&self, py: Python<'_>) -> PyResult<Vec<u8>> {
+        let work = self.take()?;
+        // Release the GIL, which sends that are still in flight need to release the
+        // buffers of their messages
+        py.allow_threads(|| work.wait())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Wait for the message and return it together with its sequence number among the
+    /// messages of its tag
+    pub fn wait_with_seq(&self, py: Python<'_>) -> PyResult<(Vec<u8>, u64)> {
+        let work = self.take()?;
+        py.allow_threads(|| work.wait_with_seq())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Wait for the message and return it as a one-dimensional uint8 tensor that can be
+    /// wrapped via DLPack without copying it (e.g. with `torch.from_dlpack`)
+    pub fn wait_dlpack(&self, py: Python<'_>) -> PyResult<DLPackTensor> {
+        let msg = self.wait(py)?;
+        let len = msg.len();
+        IrohTensor::new(DType::U8, vec![len], msg)
+            .map(DLPackTensor::new)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
+
+#[pyclass]
+pub struct RecvIntoWork {
+    inner: RwLock<Option<Result<IrohRecvWork<PyRecvBuffer>>>>,
+}
+
+impl RecvIntoWork {
+    fn new(inner: Result<IrohRecvWork<PyRecvBuffer>>) -> Self {
+        Self {
+            inner: RwLock::new(Some(inner)),
+        }
+    }
+}
+
+#[pymethods]
+impl RecvIntoWork {
+    /// Wait for the message to be received into the buffer
+    pub fn wait(&self, py: Python<'_>) -> PyResult<()> {
         // Take the inner value out of the RwLock, leaving None in its place
         let mut write_guard = self
             .inner
             .write()
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         if let Some(inner) = write_guard.take() {
-            inner
-                .unwrap()
-                .wait()
+            // Release the GIL, which is needed to release the buffer on failure. On
+            // success, the buffer is released here (while holding the GIL)
+            py.allow_threads(|| inner.and_then(|work| work.wait()))
+                .map(drop)
                 .map_err(|e| PyRuntimeError::new_err(e.to_string()))
         } else {
             Err(PyRuntimeError::new_err(
-                "RecvWork has already been consumed",
+                "RecvIntoWork has already been consumed",
             ))
         }
     }
 }
 
+// Work of a receive from any tag, resolving to the tag and the message
+type IrohRecvAnyWork = IrohRecvWork<(usize, Vec<u8>)>;
+
+#[pyclass]
+pub struct RecvAnyWork {
+    inner: RwLock<Option<Result<IrohRecvAnyWork>>>,
+}
+
+impl RecvAnyWork {
+    fn new(inner: Result<IrohRecvAnyWork>) -> Self {
+        Self {
+            inner: RwLock::new(Some(inner)),
+        }
+    }
+}
+
+#[pymethods]
+impl RecvAnyWork {
+    /// Wait for the message and return it together with the tag it arrived on
+    pub fn wait(&self, py: Python<'_>) -> PyResult<(usize, Vec<u8>)> {
+        // Take the inner value out of the RwLock, leaving None in its place
+        let mut write_guard = self
+            .inner
+            .write()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(inner) = write_guard.take() {
+            py.allow_threads(|| inner.and_then(|work| work.wait()))
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "RecvAnyWork has already been consumed",
+            ))
+        }
+    }
+}
+
+#[pyclass]
+pub struct RecvTensorWork {
+    inner: RwLock<Option<Result<IrohRecvWork<IrohTensor>>>>,
+    format: TensorFormat,
+}
+
+impl RecvTensorWork {
+    fn new(inner: Result<IrohRecvWork<IrohTensor>>, format: TensorFormat) -> Self {
+        Self {
+            inner: RwLock::new(Some(inner)),
+            format,
+        }
+    }
+}
+
+#[pymethods]
+impl RecvTensorWork {
+    /// Wait for the tensor and return it as a numpy array or torch tensor
+    pub fn wait(&self, py: Python<'_>) -> PyResult<PyObject> {
+        // Take the inner value out of the RwLock, leaving None in its place
+        let mut write_guard = self
+            .inner
+            .write()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(inner) = write_guard.take() {
+            let tensor = py
+                .allow_threads(|| inner.and_then(|work| work.wait()))
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            self.format.to_py(py, tensor)
+        } else {
+            Err(PyRuntimeError::new_err(
+                "RecvTensorWork has already been consumed",
+            ))
+        }
+    }
+}
+
+#[pyclass]
+pub struct RecvTensorsWork {
+    inner: RwLock<Option<Result<IrohRecvWork<Vec<IrohTensor>>>>>,
+    format: TensorFormat,
+}
+
+impl RecvTensorsWork {
+    fn new(inner: Result<IrohRecvWork<Vec<IrohTensor>>>, format: TensorFormat) -> Self {
+        Self {
+            inner: RwLock::new(Some(inner)),
+            format,
+        }
+    }
+}
+
+#[pymethods]
+impl RecvTensorsWork {
+    /// Wait for the tensors and return them as a dict from names to tensors
+    pub fn wait(&self, py: Python<'_>) -> PyResult<Py<PyDict>> {
+        // Take the inner value out of the RwLock, leaving None in its place
+        let mut write_guard = self
+            .inner
+            .write()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(inner) = write_guard.take() {
+            let tensors = py
+                .allow_threads(|| inner.and_then(|work| work.wait()))
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            let dict = PyDict::new(py);
+            for tensor in tensors {
+                dict.set_item(tensor.name.clone(), self.format.to_py(py, tensor)?)?;
+            }
+            Ok(dict.unbind())
+        } else {
+            Err(PyRuntimeError::new_err(
+                "RecvTensorsWork has already been consumed",
+            ))
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct NodeConfig {
+    inner: IrohNodeConfig,
+}
+
+#[pymethods]
+impl NodeConfig {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (num_streams, seed=None, discovery="n0", relay=true, auto_reconnect=false, allowed_peers=None, cluster_token=None, max_recv_streams=None, max_message_size=None, compression="none", compression_tags=None, compression_threshold=None, tensor_precision="full", tensor_precision_tags=None, checksum="none"))]
+    pub fn new(
+        num_streams: usize,
+        seed: Option<u64>,
+        discovery: &str,
+        relay: bool,
+        auto_reconnect: bool,
+        allowed_peers: Option<Vec<String>>,
+        cluster_token: Option<String>,
+        max_recv_streams: Option<usize>,
+        max_message_size: Option<usize>,
+        compression: &str,
+        compression_tags: Option<HashMap<usize, String>>,
+        compression_threshold: Option<usize>,
+        tensor_precision: &str,
+        tensor_precision_tags: Option<HashMap<usize, String>>,
+        checksum: &str,
+    ) -> PyResult<Self> {
+        let allowed_peers = allowed_peers
+            .map(|peers| {
+                peers
+                    .iter()
+                    .map(|peer_id_str| node::parse_node_id(peer_id_str))
+                    .collect::<Result<HashSet<_>>>()
+            })
+            .transpose()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let parse_codec = |codec: &str| {
+            Codec::from_str(codec).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        };
+        let compression = Compression {
+            codec: parse_codec(compression)?,
+            tags: compression_tags
+                .unwrap_or_default()
+                .iter()
+                .map(|(&tag, codec)| Ok((tag, parse_codec(codec)?)))
+                .collect::<PyResult<_>>()?,
+            threshold: compression_threshold.unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
+        };
+        let parse_precision = |precision: &str| {
+            Precision::from_str(precision).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        };
+        let tensor_precision = TensorPrecision {
+            precision: parse_precision(tensor_precision)?,
+            tags: tensor_precision_tags
+                .unwrap_or_default()
+                .iter()
+                .map(|(&tag, precision)| Ok((tag, parse_precision(precision)?)))
+                .collect::<PyResult<_>>()?,
+        };
+        Ok(Self {
+            inner: IrohNodeConfig {
+                seed,
+                discovery: node::Discovery::from_str(discovery)
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+                relay,
+                auto_reconnect,
+                allowed_peers,
+                cluster_token: cluster_token.map(String::into_bytes),
+                max_recv_streams,
+                max_message_size,
+                compression,
+                tensor_precision,
+                checksum: Checksum::from_str(checksum)
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+                ..IrohNodeConfig::new(num_streams)
+            },
+        })
+    }
+
+    #[staticmethod]
+    pub fn offline(num_streams: usize) -> Self {
+        Self {
+            inner: IrohNodeConfig::offline(num_streams),
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct RetryPolicy {
+    inner: IrohRetryPolicy,
+}
+
+impl RetryPolicy {
+    // Combine with the number of retries passed to `connect`
+    fn with_retries(policy: Option<PyRef<'_, RetryPolicy>>, num_retries: usize) -> IrohRetryPolicy {
+        IrohRetryPolicy {
+            max_retries: num_retries,
+            ..policy
+                .map(|policy| policy.inner.clone())
+                .unwrap_or_default()
+        }
+    }
+}
+
+#[pymethods]
+impl RetryPolicy {
+    #[new]
+    #[pyo3(signature = (initial_delay=0.1, backoff=2.0, jitter=0.1, max_delay=10.0, deadline=None))]
+    pub fn new(
+        initial_delay: f64,
+        backoff: f64,
+        jitter: f64,
+        max_delay: f64,
+        deadline: Option<f64>,
+    ) -> PyResult<Self> {
+        let to_duration = |secs: f64| {
+            Duration::try_from_secs_f64(secs).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        };
+        let inner = IrohRetryPolicy {
+            initial_delay: to_duration(initial_delay)?,
+            backoff,
+            jitter,
+            max_delay: to_duration(max_delay)?,
+            deadline: deadline.map(to_duration).transpose()?,
+            ..IrohRetryPolicy::default()
+        };
+        inner
+            .validate()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+}
+
 #[pyclass]
 pub struct Node {
     inner: IrohNode,
@@ -114,16 +720,107 @@ impl Node {
         })
     }
 
+    #[staticmethod]
+    pub fn with_config(config: PyRef<'_, NodeConfig>) -> PyResult<Self> {
+        Ok(Self {
+            inner: IrohNode::with_config(config.inner.clone())
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+        })
+    }
+
     pub fn node_id(&self) -> String {
         self.inner.node_id().to_string()
     }
 
-    pub fn connect(&mut self, peer_id_str: String, num_retries: usize) -> PyResult<()> {
+    pub fn ticket(&self) -> PyResult<String> {
+        self.inner
+            .ticket()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn direct_addresses(&self) -> PyResult<Vec<String>> {
+        let node_addr = self
+            .inner
+            .node_addr()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(node_addr
+            .direct_addresses()
+            .map(|addr| addr.to_string())
+            .collect())
+    }
+
+    pub fn relay_url(&self) -> PyResult<Option<String>> {
+        let node_addr = self
+            .inner
+            .node_addr()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(node_addr.relay_url().map(|url| url.to_string()))
+    }
+
+    #[pyo3(signature = (peer_id_str, num_retries, retry_policy=None))]
+    pub fn connect(
+        &mut self,
+        peer_id_str: String,
+        num_retries: usize,
+        retry_policy: Option<PyRef<'_, RetryPolicy>>,
+    ) -> PyResult<()> {
+        self.inner
+            .connect(
+                peer_id_str,
+                RetryPolicy::with_retries(retry_policy, num_retries),
+            )
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (peer_id_str, direct_addresses, num_retries, relay_url=None, retry_policy=None))]
+    pub fn connect_addr(
+        &mut self,
+        peer_id_str: String,
+        direct_addresses: Vec<String>,
+        num_retries: usize,
+        relay_url: Option<String>,
+        retry_policy: Option<PyRef<'_, RetryPolicy>>,
+    ) -> PyResult<()> {
+        let peer_addr = parse_node_addr(&peer_id_str, &direct_addresses, relay_url.as_deref())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         self.inner
-            .connect(peer_id_str, num_retries)
+            .connect_addr(
+                peer_addr,
+                RetryPolicy::with_retries(retry_policy, num_retries),
+            )
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 
+    #[pyo3(signature = (peer_id_str, num_retries, retry_policy=None))]
+    pub fn iconnect(
+        &mut self,
+        peer_id_str: String,
+        num_retries: usize,
+        retry_policy: Option<PyRef<'_, RetryPolicy>>,
+    ) -> PyResult<ConnectWork> {
+        Ok(ConnectWork::new(self.inner.iconnect(
+            peer_id_str,
+            RetryPolicy::with_retries(retry_policy, num_retries),
+        )))
+    }
+
+    #[pyo3(signature = (peer_id_str, direct_addresses, num_retries, relay_url=None, retry_policy=None))]
+    pub fn iconnect_addr(
+        &mut self,
+        peer_id_str: String,
+        direct_addresses: Vec<String>,
+        num_retries: usize,
+        relay_url: Option<String>,
+        retry_policy: Option<PyRef<'_, RetryPolicy>>,
+    ) -> PyResult<ConnectWork> {
+        let peer_addr = parse_node_addr(&peer_id_str, &direct_addresses, relay_url.as_deref())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(ConnectWork::new(self.inner.iconnect_addr(
+            peer_addr,
+            RetryPolicy::with_retries(retry_policy, num_retries),
+        )))
+    }
+
     pub fn can_recv(&self) -> bool {
         self.inner.can_recv()
     }
@@ -137,19 +834,204 @@ impl Node {
     pub fn is_ready(&self) -> bool {
         self.inner.is_ready()
     }
+
+    pub fn send_peers(&self) -> Vec<String> {
+        self.inner.send_peers()
+    }
+
+    pub fn recv_peers(&self) -> Vec<String> {
+        self.inner.recv_peers()
+    }
+
     pub fn isend(
         &mut self,
-        msg: Vec<u8>,
+        msg: &Bound<'_, PyAny>,
         tag: usize,
         latency: Option<usize>,
     ) -> PyResult<SendWork> {
+        let msg = PyMessage::new(msg)?;
         Ok(SendWork::new(self.inner.isend(msg, tag, latency)))
     }
 
+    #[pyo3(signature = (peer_id_str, msg, tag, latency=None))]
+    pub fn isend_to(
+        &mut self,
+        peer_id_str: String,
+        msg: &Bound<'_, PyAny>,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> PyResult<SendWork> {
+        let msg = PyMessage::new(msg)?;
+        Ok(SendWork::new(self.inner.isend_to(
+            &peer_id_str,
+            msg,
+            tag,
+            latency,
+        )))
+    }
+
     pub fn irecv(&mut self, tag: usize) -> PyResult<RecvWork> {
         Ok(RecvWork::new(self.inner.irecv(tag)))
     }
 
+    pub fn irecv_from(&mut self, peer_id_str: String, tag: usize) -> PyResult<RecvWork> {
+        Ok(RecvWork::new(self.inner.irecv_from(&peer_id_str, tag)))
+    }
+
+    pub fn irecv_into(&mut self, tag: usize, buffer: &Bound<'_, PyAny>) -> PyResult<RecvIntoWork> {
+        let buffer = PyRecvBuffer::new(buffer)?;
+        Ok(RecvIntoWork::new(self.inner.irecv_into(tag, buffer)))
+    }
+
+    pub fn irecv_from_into(
+        &mut self,
+        peer_id_str: String,
+        tag: usize,
+        buffer: &Bound<'_, PyAny>,
+    ) -> PyResult<RecvIntoWork> {
+        let buffer = PyRecvBuffer::new(buffer)?;
+        Ok(RecvIntoWork::new(self.inner.irecv_from_into(
+            &peer_id_str,
+            tag,
+            buffer,
+        )))
+    }
+
+    pub fn irecv_any(&mut self) -> PyResult<RecvAnyWork> {
+        Ok(RecvAnyWork::new(self.inner.irecv_any()))
+    }
+
+    pub fn irecv_any_from(&mut self, peer_id_str: String) -> PyResult<RecvAnyWork> {
+        Ok(RecvAnyWork::new(self.inner.irecv_any_from(&peer_id_str)))
+    }
+
+    pub fn iprobe(&self, tag: usize) -> PyResult<Option<usize>> {
+        self.inner
+            .iprobe(tag)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn iprobe_from(&self, peer_id_str: String, tag: usize) -> PyResult<Option<usize>> {
+        self.inner
+            .iprobe_from(&peer_id_str, tag)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn probe(&self, py: Python<'_>, tag: usize) -> PyResult<usize> {
+        // Release the GIL while waiting, like for receives
+        py.allow_threads(|| self.inner.probe(tag))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn probe_from(&self, py: Python<'_>, peer_id_str: String, tag: usize) -> PyResult<usize> {
+        py.allow_threads(|| self.inner.probe_from(&peer_id_str, tag))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (tensor, tag, latency=None))]
+    pub fn isend_tensor(
+        &mut self,
+        py: Python<'_>,
+        tensor: &Bound<'_, PyAny>,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> PyResult<SendWork> {
+        let tensor = tensor_from_py(py, tensor)?;
+        Ok(SendWork::new(
+            self.inner.isend_tensor(&tensor, tag, latency),
+        ))
+    }
+
+    #[pyo3(signature = (peer_id_str, tensor, tag, latency=None))]
+    pub fn isend_tensor_to(
+        &mut self,
+        py: Python<'_>,
+        peer_id_str: String,
+        tensor: &Bound<'_, PyAny>,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> PyResult<SendWork> {
+        let tensor = tensor_from_py(py, tensor)?;
+        Ok(SendWork::new(self.inner.isend_tensor_to(
+            &peer_id_str,
+            &tensor,
+            tag,
+            latency,
+        )))
+    }
+
+    #[pyo3(signature = (tensors, tag, latency=None))]
+    pub fn isend_tensors(
+        &mut self,
+        py: Python<'_>,
+        tensors: &Bound<'_, PyDict>,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> PyResult<SendWork> {
+        let tensors = tensors_from_py(py, tensors)?;
+        Ok(SendWork::new(
+            self.inner.isend_tensors(&tensors, tag, latency),
+        ))
+    }
+
+    #[pyo3(signature = (peer_id_str, tensors, tag, latency=None))]
+    pub fn isend_tensors_to(
+        &mut self,
+        py: Python<'_>,
+        peer_id_str: String,
+        tensors: &Bound<'_, PyDict>,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> PyResult<SendWork> {
+        let tensors = tensors_from_py(py, tensors)?;
+        Ok(SendWork::new(self.inner.isend_tensors_to(
+            &peer_id_str,
+            &tensors,
+            tag,
+            latency,
+        )))
+    }
+
+    #[pyo3(signature = (tag, format="numpy"))]
+    pub fn irecv_tensors(&mut self, tag: usize, format: &str) -> PyResult<RecvTensorsWork> {
+        let format = TensorFormat::from_str(format)?;
+        Ok(RecvTensorsWork::new(self.inner.irecv_tensors(tag), format))
+    }
+
+    #[pyo3(signature = (peer_id_str, tag, format="numpy"))]
+    pub fn irecv_tensors_from(
+        &mut self,
+        peer_id_str: String,
+        tag: usize,
+        format: &str,
+    ) -> PyResult<RecvTensorsWork> {
+        let format = TensorFormat::from_str(format)?;
+        Ok(RecvTensorsWork::new(
+            self.inner.irecv_tensors_from(&peer_id_str, tag),
+            format,
+        ))
+    }
+
+    #[pyo3(signature = (tag, format="numpy"))]
+    pub fn irecv_tensor(&mut self, tag: usize, format: &str) -> PyResult<RecvTensorWork> {
+        let format = TensorFormat::from_str(format)?;
+        Ok(RecvTensorWork::new(self.inner.irecv_tensor(tag), format))
+    }
+
+    #[pyo3(signature = (peer_id_str, tag, format="numpy"))]
+    pub fn irecv_tensor_from(
+        &mut self,
+        peer_id_str: String,
+        tag: usize,
+        format: &str,
+    ) -> PyResult<RecvTensorWork> {
+        let format = TensorFormat::from_str(format)?;
+        Ok(RecvTensorWork::new(
+            self.inner.irecv_tensor_from(&peer_id_str, tag),
+            format,
+        ))
+    }
+
     pub fn close(&mut self) -> PyResult<()> {
         self.inner
             .close()
@@ -169,8 +1051,16 @@ fn _prime_iroh(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
         env_logger::init();
     });
 
+    m.add_class::<ConnectWork>()?;
     m.add_class::<SendWork>()?;
     m.add_class::<RecvWork>()?;
+    m.add_class::<RecvIntoWork>()?;
+    m.add_class::<RecvAnyWork>()?;
+    m.add_class::<RecvTensorWork>()?;
+    m.add_class::<RecvTensorsWork>()?;
+    m.add_class::<DLPackTensor>()?;
+    m.add_class::<NodeConfig>()?;
+    m.add_class::<RetryPolicy>()?;
     m.add_class::<Node>()?;
     Ok(())
 }
@@ -1,16 +1,164 @@
-use crate::receiver::Receiver;
-use crate::sender::Sender;
-use crate::work::{RecvWork, SendWork};
+use crate::frame::{Checksum, Compression};
+use crate::handshake::transport_config;
+use crate::precision::TensorPrecision;
+use crate::receiver::{Buffer, Receiver};
+use crate::retry::RetryPolicy;
+use crate::sender::{Message, Sender};
+use crate::tensor::Tensor;
+use crate::work::{ConnectWork, RecvWork, SendWork};
 
-use anyhow::{Error, Result};
-use iroh::{Endpoint, SecretKey};
+use anyhow::{Error, Result, anyhow};
+use iroh::{Endpoint, NodeAddr, NodeId, RelayMode, RelayUrl, SecretKey};
+use iroh_base::ticket::NodeTicket;
 use rand::SeedableRng;
 use rand::rngs::StdRng;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
 
+// Prefix of serialized node tickets (cannot be confused with a hex-encoded node id)
+const NODE_TICKET_PREFIX: &str = "node";
+
+/// Parse a hex-encoded node id, as returned by [`Node::node_id`]
+pub fn parse_node_id(node_id_str: &str) -> Result<NodeId> {
+    let bytes = hex::decode(node_id_str)?;
+    Ok(NodeId::from_bytes(bytes.as_slice().try_into()?)?)
+}
+
+/// Parse a peer given either as a hex-encoded node id or as a node ticket, as returned
+/// by [`Node::ticket`]
+pub fn parse_peer_addr(peer_str: &str) -> Result<NodeAddr> {
+    if peer_str.starts_with(NODE_TICKET_PREFIX) {
+        let ticket = NodeTicket::from_str(peer_str)?;
+        Ok(ticket.into())
+    } else {
+        Ok(NodeAddr::new(parse_node_id(peer_str)?))
+    }
+}
+
+/// Build a full peer address from a hex-encoded node id, direct socket addresses and
+/// an optional relay URL
+pub fn parse_node_addr(
+    node_id_str: &str,
+    direct_addresses: &[String],
+    relay_url: Option<&str>,
+) -> Result<NodeAddr> {
+    let node_id = parse_node_id(node_id_str)?;
+    let direct_addresses = direct_addresses
+        .iter()
+        .map(|addr| SocketAddr::from_str(addr))
+        .collect::<Result<Vec<_>, _>>()?;
+    let relay_url = relay_url.map(RelayUrl::from_str).transpose()?;
+    Ok(NodeAddr::from_parts(node_id, relay_url, direct_addresses))
+}
+
+/// How a node resolves the addresses of peers it only knows by node id
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Discovery {
+    /// Publish and resolve addresses via the n0 discovery service (requires internet access)
+    #[default]
+    N0,
+    /// Publish and resolve addresses via mDNS, which finds peers on the same local
+    /// network (or host) without any external service
+    Local,
+    /// No discovery, peers have to be connected to with their full address
+    Disabled,
+}
+
+impl FromStr for Discovery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "n0" => Ok(Self::N0),
+            "local" | "mdns" => Ok(Self::Local),
+            "none" | "disabled" => Ok(Self::Disabled),
+            _ => Err(anyhow!(
+                "Invalid discovery mode '{}', expected 'n0', 'local' or 'none'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    /// Number of streams opened when connecting, for tags `0..num_streams`. Streams of
+    /// other tags are opened on first use
+    pub num_streams: usize,
+    /// Maximum number of streams accepted per incoming connection, defaults to
+    /// `num_streams`. The receiver adopts the number of streams of each sender up to
+    /// this maximum and rejects senders with more streams
+    pub max_recv_streams: Option<usize>,
+    /// Optional seed for generating the secret/public key
+    pub seed: Option<u64>,
+    /// How peer addresses are resolved
+    pub discovery: Discovery,
+    /// Whether to use the default n0 relay servers
+    pub relay: bool,
+    /// Whether to re-establish lost connections: the sender re-dials with the retry
+    /// policy used to connect and the receiver accepts replacement connections
+    pub auto_reconnect: bool,
+    /// Optional set of peers that are allowed to connect to this node, connections
+    /// from any other peer are rejected before any stream is accepted
+    pub allowed_peers: Option<HashSet<NodeId>>,
+    /// Optional shared secret of the cluster: senders prove knowledge of it when
+    /// connecting and receivers reject peers that cannot
+    pub cluster_token: Option<Vec<u8>>,
+    /// Optional maximum size of received messages in bytes. Larger messages fail to be
+    /// received and reset the stream of their tag, instead of being allocated
+    pub max_message_size: Option<usize>,
+    /// Compression of sent messages, by default none. Received messages are decompressed
+    /// whatever their codec
+    pub compression: Compression,
+    /// Precision of float32 tensors sent with `isend_tensor`, by default full. Received
+    /// tensors are restored to float32 whatever their precision
+    pub tensor_precision: TensorPrecision,
+    /// Checksum appended to sent messages, by default none. Received messages are
+    /// verified against their checksum (if any), failing the receive on a mismatch
+    pub checksum: Checksum,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl NodeConfig {
+    pub fn new(num_streams: usize) -> Self {
+        Self {
+            num_streams,
+            max_recv_streams: None,
+            seed: None,
+            discovery: Discovery::default(),
+            relay: true,
+            auto_reconnect: false,
+            allowed_peers: None,
+            cluster_token: None,
+            max_message_size: None,
+            compression: Compression::default(),
+            tensor_precision: TensorPrecision::default(),
+            checksum: Checksum::None,
+        }
+    }
+
+    /// Configuration for networks without internet access: discovery and relays are
+    /// disabled, so peers have to be connected to via [`Node::connect_addr`]
+    pub fn offline(num_streams: usize) -> Self {
+        Self {
+            discovery: Discovery::Disabled,
+            relay: false,
+            ..Self::new(num_streams)
+        }
+    }
+}
+
 pub struct Node {
     num_streams: usize,
+    runtime: Arc<Runtime>,
     endpoint: Endpoint,
     receiver: Receiver,
     sender: Sender,
@@ -22,11 +170,26 @@ impl Node {
     }
 
     pub fn with_seed(num_streams: usize, seed: Option<u64>) -> Result<Self> {
+        Self::with_config(NodeConfig {
+            seed,
+            ..NodeConfig::new(num_streams)
+        })
+    }
+
+    pub fn with_config(config: NodeConfig) -> Result<Self> {
         log::info!("Creating node");
         let runtime = Arc::new(Runtime::new()?);
         let endpoint = runtime.block_on(async {
-            let mut builder = Endpoint::builder().discovery_n0();
-            if let Some(seed) = seed {
+            let mut builder = Endpoint::builder().transport_config(transport_config());
+            match config.discovery {
+                Discovery::N0 => builder = builder.discovery_n0(),
+                Discovery::Local => builder = builder.discovery_local_network(),
+                Discovery::Disabled => {}
+            }
+            if !config.relay {
+                builder = builder.relay_mode(RelayMode::Disabled);
+            }
+            if let Some(seed) = config.seed {
                 let mut rng = StdRng::seed_from_u64(seed);
                 let secret_key = SecretKey::generate(&mut rng);
                 builder = builder.secret_key(secret_key);
@@ -34,11 +197,12 @@ impl Node {
             let endpoint = builder.bind().await?;
             Ok::<Endpoint, Error>(endpoint)
         })?;
-        let receiver = Receiver::new(runtime.clone(), endpoint.clone(), num_streams);
-        let sender = Sender::new(runtime.clone(), endpoint.clone());
+        let receiver = Receiver::with_config(runtime.clone(), endpoint.clone(), &config);
+        let sender = Sender::with_config(runtime.clone(), endpoint.clone(), &config);
         log::info!("Created node (ID={})", endpoint.node_id().fmt_short());
         Ok(Self {
-            num_streams,
+            num_streams: config.num_streams,
+            runtime,
             endpoint,
             receiver,
             sender,
@@ -49,12 +213,62 @@ impl Node {
         self.endpoint.node_id().to_string()
     }
 
-    pub fn connect(&mut self, peer_id_str: String, num_retries: usize) -> Result<()> {
+    /// Get the full address of this node, i.e. its node id, direct addresses and relay URL
+    pub fn node_addr(&self) -> Result<NodeAddr> {
+        self.runtime.block_on(self.endpoint.node_addr())
+    }
+
+    /// Get a ticket containing the full address of this node, which peers can pass to
+    /// [`Node::connect`] instead of the node id to connect without discovery
+    pub fn ticket(&self) -> Result<String> {
+        Ok(NodeTicket::new(self.node_addr()?).to_string())
+    }
+
+    /// Connect to a peer given either by its node id or by its ticket. Accepts a
+    /// [`RetryPolicy`] or just the number of retries, which uses the default backoff
+    pub fn connect(
+        &mut self,
+        peer_id_str: String,
+        retry_policy: impl Into<RetryPolicy>,
+    ) -> Result<()> {
+        self.sender
+            .connect(peer_id_str, self.num_streams, &retry_policy.into())?;
+        Ok(())
+    }
+
+    /// Connect to a peer via its full address, which does not require discovery
+    pub fn connect_addr(
+        &mut self,
+        peer_addr: NodeAddr,
+        retry_policy: impl Into<RetryPolicy>,
+    ) -> Result<()> {
         self.sender
-            .connect(peer_id_str, self.num_streams, num_retries)?;
+            .connect_addr(peer_addr, self.num_streams, &retry_policy.into())?;
         Ok(())
     }
 
+    /// Like [`Node::connect`], but returns immediately with a work that completes once
+    /// the connection is established
+    pub fn iconnect(
+        &mut self,
+        peer_id_str: String,
+        retry_policy: impl Into<RetryPolicy>,
+    ) -> Result<ConnectWork> {
+        self.sender
+            .iconnect(peer_id_str, self.num_streams, &retry_policy.into())
+    }
+
+    /// Like [`Node::connect_addr`], but returns immediately with a work that completes
+    /// once the connection is established
+    pub fn iconnect_addr(
+        &mut self,
+        peer_addr: NodeAddr,
+        retry_policy: impl Into<RetryPolicy>,
+    ) -> Result<ConnectWork> {
+        self.sender
+            .iconnect_addr(peer_addr, self.num_streams, &retry_policy.into())
+    }
+
     pub fn can_recv(&self) -> bool {
         self.receiver.is_ready()
     }
@@ -67,14 +281,165 @@ impl Node {
         self.can_recv() && self.can_send()
     }
 
-    pub fn isend(&mut self, msg: Vec<u8>, tag: usize, latency: Option<usize>) -> Result<SendWork> {
+    pub fn send_peers(&self) -> Vec<String> {
+        self.sender
+            .peers()
+            .iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    pub fn recv_peers(&self) -> Vec<String> {
+        self.receiver
+            .peers()
+            .iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Send a message to the only connected peer. The message is sent directly from its
+    /// memory, which is held until the returned work completes
+    pub fn isend(
+        &mut self,
+        msg: impl Message,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
         self.sender.isend(msg, tag, latency)
     }
 
+    pub fn isend_to(
+        &mut self,
+        peer_id_str: &str,
+        msg: impl Message,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.sender
+            .isend_to(parse_node_id(peer_id_str)?, msg, tag, latency)
+    }
+
     pub fn irecv(&mut self, tag: usize) -> Result<RecvWork> {
         self.receiver.irecv(tag)
     }
 
+    pub fn irecv_from(&mut self, peer_id_str: &str, tag: usize) -> Result<RecvWork> {
+        self.receiver.irecv_from(parse_node_id(peer_id_str)?, tag)
+    }
+
+    /// Receive a message into a caller-provided buffer of exactly its size, which is
+    /// returned once the receive completes
+    pub fn irecv_into<B: Buffer>(&mut self, tag: usize, buffer: B) -> Result<RecvWork<B>> {
+        self.receiver.irecv_into(tag, buffer)
+    }
+
+    pub fn irecv_from_into<B: Buffer>(
+        &mut self,
+        peer_id_str: &str,
+        tag: usize,
+        buffer: B,
+    ) -> Result<RecvWork<B>> {
+        self.receiver
+            .irecv_from_into(parse_node_id(peer_id_str)?, tag, buffer)
+    }
+
+    /// Receive the next message of whichever tag it arrives on first, together with
+    /// the tag
+    pub fn irecv_any(&mut self) -> Result<RecvWork<(usize, Vec<u8>)>> {
+        self.receiver.irecv_any()
+    }
+
+    pub fn irecv_any_from(&mut self, peer_id_str: &str) -> Result<RecvWork<(usize, Vec<u8>)>> {
+        self.receiver.irecv_any_from(parse_node_id(peer_id_str)?)
+    }
+
+    /// Size of the next message of a tag if it already arrived, without receiving it
+    pub fn iprobe(&self, tag: usize) -> Result<Option<usize>> {
+        self.receiver.iprobe(tag)
+    }
+
+    pub fn iprobe_from(&self, peer_id_str: &str, tag: usize) -> Result<Option<usize>> {
+        self.receiver.iprobe_from(parse_node_id(peer_id_str)?, tag)
+    }
+
+    /// Wait for the next message of a tag and return its size, without receiving it
+    pub fn probe(&self, tag: usize) -> Result<usize> {
+        self.receiver.probe(tag)
+    }
+
+    pub fn probe_from(&self, peer_id_str: &str, tag: usize) -> Result<usize> {
+        self.receiver.probe_from(parse_node_id(peer_id_str)?, tag)
+    }
+
+    /// Send a tensor together with its dtype, shape and strides (copying its data into
+    /// the message). Float32 tensors are downcast to the precision of the tag
+    pub fn isend_tensor(
+        &mut self,
+        tensor: &Tensor,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.sender.isend_tensor(tensor, tag, latency)
+    }
+
+    pub fn isend_tensor_to(
+        &mut self,
+        peer_id_str: &str,
+        tensor: &Tensor,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.sender
+            .isend_tensor_to(parse_node_id(peer_id_str)?, tensor, tag, latency)
+    }
+
+    /// Receive a tensor sent with `isend_tensor`, failing if the message is not a
+    /// valid tensor
+    pub fn irecv_tensor(&mut self, tag: usize) -> Result<RecvWork<Tensor>> {
+        Ok(self.receiver.irecv(tag)?.map(Tensor::decode))
+    }
+
+    pub fn irecv_tensor_from(&mut self, peer_id_str: &str, tag: usize) -> Result<RecvWork<Tensor>> {
+        Ok(self
+            .receiver
+            .irecv_from(parse_node_id(peer_id_str)?, tag)?
+            .map(Tensor::decode))
+    }
+
+    /// Send named tensors as a single message in the safetensors layout
+    pub fn isend_tensors(
+        &mut self,
+        tensors: &[Tensor],
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.sender.isend_tensors(tensors, tag, latency)
+    }
+
+    pub fn isend_tensors_to(
+        &mut self,
+        peer_id_str: &str,
+        tensors: &[Tensor],
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.sender
+            .isend_tensors_to(parse_node_id(peer_id_str)?, tensors, tag, latency)
+    }
+
+    pub fn irecv_tensors(&mut self, tag: usize) -> Result<RecvWork<Vec<Tensor>>> {
+        self.receiver.irecv_tensors(tag)
+    }
+
+    pub fn irecv_tensors_from(
+        &mut self,
+        peer_id_str: &str,
+        tag: usize,
+    ) -> Result<RecvWork<Vec<Tensor>>> {
+        self.receiver
+            .irecv_tensors_from(parse_node_id(peer_id_str)?, tag)
+    }
+
     pub fn close(&mut self) -> Result<()> {
         log::info!("Closing node (ID={})", self.endpoint.node_id().fmt_short());
         self.sender.close()?;
@@ -95,6 +460,79 @@ mod tests {
         assert!(!node.can_recv());
         assert!(!node.can_send());
         assert!(!node.is_ready());
+        assert!(node.send_peers().is_empty());
+        assert!(node.recv_peers().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_node_id() -> Result<()> {
+        let node = Node::new(1)?;
+        assert_eq!(parse_node_id(&node.node_id())?, node.endpoint.node_id());
+        assert!(parse_node_id("not-a-node-id").is_err());
+        assert!(parse_node_id("abcd").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_node_addr() -> Result<()> {
+        let node = Node::new(1)?;
+        let addrs = vec!["127.0.0.1:4242".to_string(), "[::1]:4242".to_string()];
+        let node_addr = parse_node_addr(&node.node_id(), &addrs, None)?;
+        assert_eq!(node_addr.node_id, node.endpoint.node_id());
+        assert_eq!(node_addr.direct_addresses.len(), 2);
+        assert!(node_addr.relay_url.is_none());
+
+        let node_addr = parse_node_addr(&node.node_id(), &[], Some("https://relay.example.com"))?;
+        assert!(node_addr.direct_addresses.is_empty());
+        assert!(node_addr.relay_url.is_some());
+
+        assert!(parse_node_addr(&node.node_id(), &["localhost".to_string()], None).is_err());
+        assert!(parse_node_addr(&node.node_id(), &[], Some("not a url")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_peer_addr() -> Result<()> {
+        let node = Node::with_config(NodeConfig::offline(1))?;
+
+        // Bare node id
+        let peer_addr = parse_peer_addr(&node.node_id())?;
+        assert_eq!(peer_addr, NodeAddr::new(node.endpoint.node_id()));
+
+        // Node ticket
+        let ticket = node.ticket()?;
+        assert!(ticket.starts_with(NODE_TICKET_PREFIX));
+        let peer_addr = parse_peer_addr(&ticket)?;
+        assert_eq!(peer_addr, node.node_addr()?);
+
+        assert!(parse_peer_addr("nodeinvalid").is_err());
+        assert!(parse_peer_addr("invalid").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_discovery_from_str() -> Result<()> {
+        assert_eq!(Discovery::from_str("n0")?, Discovery::N0);
+        assert_eq!(Discovery::from_str("local")?, Discovery::Local);
+        assert_eq!(Discovery::from_str("none")?, Discovery::Disabled);
+        assert!(Discovery::from_str("dht").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_offline_node_creation() -> Result<()> {
+        let node = Node::with_config(NodeConfig::offline(1))?;
+        let node_addr = node.node_addr()?;
+        assert_eq!(node_addr.node_id.to_string(), node.node_id());
+        assert!(!node_addr.direct_addresses.is_empty());
+        assert!(node_addr.relay_url.is_none());
+        assert!(!node.is_ready());
 
         Ok(())
     }
@@ -0,0 +1,413 @@
+use anyhow::{Result, anyhow, bail, ensure};
+use half::{bf16, f16};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::tensor::{ByteOrder, DType, Tensor, contiguous_strides};
+
+/// Number of elements sharing a scale factor in FP8 tensors
+pub const FP8_BLOCK_SIZE: usize = 128;
+
+// Largest finite value of the FP8 (E4M3) format
+const FP8_MAX: f32 = 448.0;
+
+/// Precision of float32 tensors on the wire. Senders downcast float32 tensors to it and
+/// receivers restore them to float32, tensors of all other dtypes are sent as is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Precision {
+    #[default]
+    Full,
+    BF16,
+    F16,
+    /// FP8 (E4M3) with a float32 scale factor per block of `FP8_BLOCK_SIZE` elements
+    FP8,
+}
+
+impl Precision {
+    pub(crate) fn code(&self) -> u8 {
+        match self {
+            Precision::Full => 0,
+            Precision::BF16 => 1,
+            Precision::F16 => 2,
+            Precision::FP8 => 3,
+        }
+    }
+
+    pub(crate) fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(Precision::Full),
+            1 => Ok(Precision::BF16),
+            2 => Ok(Precision::F16),
+            3 => Ok(Precision::FP8),
+            _ => bail!("Unknown tensor precision {}", code),
+        }
+    }
+
+    // Size of the downcast data of a tensor with the given number of elements
+    fn data_len(&self, numel: usize) -> Option<usize> {
+        match self {
+            Precision::Full => numel.checked_mul(4),
+            Precision::BF16 | Precision::F16 => numel.checked_mul(2),
+            Precision::FP8 => numel
+                .div_ceil(FP8_BLOCK_SIZE)
+                .checked_mul(4)?
+                .checked_add(numel),
+        }
+    }
+
+    /// Encode a tensor as a message at this precision, i.e. the header of the float32
+    /// tensor followed by its downcast data (for FP8, the scale factors of all blocks
+    /// followed by the elements). Returns `None` if the tensor is sent as is anyway
+    pub fn downcast(&self, tensor: &Tensor) -> Result<Option<Vec<u8>>> {
+        if *self == Precision::Full || tensor.dtype != DType::F32 {
+            return Ok(None);
+        }
+        tensor.validate()?;
+        let tensor = match tensor.is_contiguous() {
+            true => Cow::Borrowed(tensor),
+            false => Cow::Owned(tensor.clone().into_native()),
+        };
+        let byte_order = tensor.byte_order;
+        let numel = tensor.numel().unwrap_or_default();
+        let mut data = Vec::with_capacity(self.data_len(numel).unwrap_or_default());
+        match self {
+            Precision::Full => unreachable!(),
+            Precision::BF16 => {
+                for value in read_f32s(&tensor.data, byte_order) {
+                    data.extend_from_slice(&bf16::from_f32(value).to_le_bytes());
+                }
+            }
+            Precision::F16 => {
+                for value in read_f32s(&tensor.data, byte_order) {
+                    data.extend_from_slice(&f16::from_f32(value).to_le_bytes());
+                }
+            }
+            Precision::FP8 => {
+                // Scale every block so that its largest (finite) element maps to the
+                // largest FP8 value
+                let blocks = || tensor.data.chunks(4 * FP8_BLOCK_SIZE);
+                let scales = blocks().map(|block| {
+                    let max = read_f32s(block, byte_order)
+                        .map(|value| value.abs())
+                        .filter(|value| value.is_finite())
+                        .fold(0.0, f32::max);
+                    Some(max / FP8_MAX)
+                        .filter(|&scale| scale > 0.0)
+                        .unwrap_or(1.0)
+                });
+                let scales = scales.collect::<Vec<_>>();
+                for scale in &scales {
+                    data.extend_from_slice(&scale.to_le_bytes());
+                }
+                for (block, scale) in blocks().zip(&scales) {
+                    data.extend(
+                        read_f32s(block, byte_order).map(|value| f32_to_e4m3(value / scale)),
+                    );
+                }
+            }
+        }
+
+        let tensor = Tensor {
+            name: tensor.name.clone(),
+            dtype: DType::F32,
+            shape: tensor.shape.clone(),
+            strides: contiguous_strides(DType::F32, &tensor.shape)
+                .ok_or_else(|| anyhow!("Tensor of shape {:?} is too large", tensor.shape))?,
+            byte_order: ByteOrder::Little,
+            data,
+        };
+        Ok(Some(tensor.encode()?))
+    }
+
+    // Parse the header of a downcast message, returning the number of elements and the
+    // offset of the data
+    fn decode_header(&self, msg: &[u8]) -> Result<(usize, usize)> {
+        let (tensor, data_offset) = Tensor::decode_header(msg)?;
+        let numel = tensor.numel();
+        let (Some(numel), Some(data_len)) = (numel, numel.and_then(|n| self.data_len(n))) else {
+            bail!("Tensor of shape {:?} is too large", tensor.shape);
+        };
+        ensure!(
+            tensor.dtype == DType::F32
+                && tensor.byte_order == ByteOrder::Little
+                && tensor.is_contiguous(),
+            "Downcast message is not a contiguous float32 tensor"
+        );
+        ensure!(
+            msg.len() - data_offset == data_len,
+            "Tensor of shape {:?} at {} precision needs {} bytes of data, got {} bytes",
+            tensor.shape,
+            self,
+            data_len,
+            msg.len() - data_offset
+        );
+        Ok((numel, data_offset))
+    }
+
+    /// Size of the float32 tensor message restored from a downcast message
+    pub fn restored_size(&self, msg: &[u8]) -> Result<usize> {
+        let (numel, data_offset) = self.decode_header(msg)?;
+        numel
+            .checked_mul(4)
+            .and_then(|data_len| data_len.checked_add(data_offset))
+            .ok_or_else(|| anyhow!("Restored tensor of {} elements is too large", numel))
+    }
+
+    /// Restore a downcast message into a float32 tensor message, filling a buffer of
+    /// exactly its restored size
+    pub fn restore_into(&self, msg: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let (numel, data_offset) = self.decode_header(msg)?;
+        ensure!(
+            Some(buffer.len()) == numel.checked_mul(4).map(|len| len + data_offset),
+            "Buffer of {} bytes does not fit the restored tensor",
+            buffer.len()
+        );
+        let (header, values) = buffer.split_at_mut(data_offset);
+        header.copy_from_slice(&msg[..data_offset]);
+        let data = &msg[data_offset..];
+
+        let values = values.chunks_exact_mut(4);
+        match self {
+            Precision::Full => {
+                for (value, element) in values.zip(data.chunks_exact(4)) {
+                    value.copy_from_slice(element);
+                }
+            }
+            Precision::BF16 => {
+                for (value, element) in values.zip(data.chunks_exact(2)) {
+                    let element = bf16::from_le_bytes([element[0], element[1]]);
+                    value.copy_from_slice(&element.to_f32().to_le_bytes());
+                }
+            }
+            Precision::F16 => {
+                for (value, element) in values.zip(data.chunks_exact(2)) {
+                    let element = f16::from_le_bytes([element[0], element[1]]);
+                    value.copy_from_slice(&element.to_f32().to_le_bytes());
+                }
+            }
+            Precision::FP8 => {
+                let (scales, elements) = data.split_at(4 * numel.div_ceil(FP8_BLOCK_SIZE));
+                let table: [f32; 256] = std::array::from_fn(|code| e4m3_to_f32(code as u8));
+                let elements = elements
+                    .chunks(FP8_BLOCK_SIZE)
+                    .zip(scales.chunks_exact(4))
+                    .flat_map(|(block, scale)| {
+                        let scale = read_f32(scale, ByteOrder::Little);
+                        block.iter().map(move |&code| table[code as usize] * scale)
+                    });
+                for (value, element) in values.zip(elements) {
+                    value.copy_from_slice(&element.to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Precision::Full => "full",
+            Precision::BF16 => "bf16",
+            Precision::F16 => "fp16",
+            Precision::FP8 => "fp8",
+        })
+    }
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(Precision::Full),
+            "bf16" => Ok(Precision::BF16),
+            "fp16" => Ok(Precision::F16),
+            "fp8" => Ok(Precision::FP8),
+            _ => Err(anyhow!(
+                "Invalid tensor precision: {}, expected 'full', 'bf16', 'fp16' or 'fp8'",
+                s
+            )),
+        }
+    }
+}
+
+/// Precision of float32 tensors sent with `isend_tensor`, for the whole node that can be
+/// overridden per tag. Receivers restore float32 tensors whatever their precision
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TensorPrecision {
+    /// Precision of tags without a precision of their own
+    pub precision: Precision,
+    /// Precisions of individual tags
+    pub tags: HashMap<usize, Precision>,
+}
+
+impl TensorPrecision {
+    /// Precision of tensors sent via a tag
+    pub fn precision(&self, tag: usize) -> Precision {
+        self.tags.get(&tag).copied().unwrap_or(self.precision)
+    }
+}
+
+fn read_f32(bytes: &[u8], byte_order: ByteOrder) -> f32 {
+    let bytes = bytes.try_into().unwrap();
+    match byte_order {
+        ByteOrder::Little => f32::from_le_bytes(bytes),
+        ByteOrder::Big => f32::from_be_bytes(bytes),
+    }
+}
+
+// Float32 values of the bytes, without collecting them
+fn read_f32s(data: &[u8], byte_order: ByteOrder) -> impl Iterator<Item = f32> + '_ {
+    data.chunks_exact(4)
+        .map(move |value| read_f32(value, byte_order))
+}
+
+// Round to the nearest FP8 (E4M3) value, saturating at the largest finite value
+fn f32_to_e4m3(value: f32) -> u8 {
+    let sign = ((value.to_bits() >> 24) & 0x80) as u8;
+    let value = value.abs();
+    if value.is_nan() {
+        return sign | 0x7f;
+    }
+    if value >= FP8_MAX {
+        return sign | 0x7e;
+    }
+
+    // Subnormals are multiples of 2^-9, below the smallest normal value of 2^-6 (a
+    // subnormal rounding up to 8 * 2^-9 yields exactly the code of that normal value)
+    if value < 2f32.powi(-6) {
+        return sign | (value * 512.0).round_ties_even() as u8;
+    }
+    let mut exponent = ((value.to_bits() >> 23) & 0xff) as i32 - 127;
+    let mut mantissa = ((value / 2f32.powi(exponent) - 1.0) * 8.0).round_ties_even() as i32;
+    if mantissa == 8 {
+        exponent += 1;
+        mantissa = 0;
+    }
+    sign | (((exponent + 7) << 3) | mantissa).min(0x7e) as u8
+}
+
+fn e4m3_to_f32(code: u8) -> f32 {
+    let sign = if code & 0x80 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((code >> 3) & 0xf) as i32;
+    let mantissa = (code & 0x7) as f32;
+    sign * match (exponent, code & 0x7) {
+        (0xf, 0x7) => f32::NAN,
+        (0, _) => mantissa * 2f32.powi(-9),
+        _ => (1.0 + mantissa / 8.0) * 2f32.powi(exponent - 7),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restore(precision: Precision, msg: &[u8]) -> Result<Tensor> {
+        let mut buffer = vec![0; precision.restored_size(msg)?];
+        precision.restore_into(msg, &mut buffer)?;
+        Tensor::decode(buffer)
+    }
+
+    #[test]
+    fn test_downcast_roundtrip() -> Result<()> {
+        // Several FP8 blocks, the last one partial, with a range of magnitudes
+        let values: Vec<f32> = (0..300).map(|i| (i as f32 - 150.0) * 0.37).collect();
+        let data = values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        let tensor = Tensor::new(DType::F32, vec![3, 100], data)?.with_name("activations");
+        let max = values
+            .iter()
+            .fold(0.0f32, |max, value| max.max(value.abs()));
+
+        for (precision, tolerance) in [
+            (Precision::BF16, 1.0 / 256.0),
+            (Precision::F16, 1.0 / 2048.0),
+            (Precision::FP8, 1.0 / 16.0),
+        ] {
+            let msg = precision.downcast(&tensor)?.unwrap();
+            assert!(msg.len() < tensor.encode()?.len());
+            let restored = restore(precision, &msg)?;
+            assert_eq!(restored.dtype, DType::F32);
+            assert_eq!(restored.shape, tensor.shape);
+            assert_eq!(restored.name, tensor.name);
+            let restored = restored.into_native();
+            for (value, element) in values.iter().zip(restored.data.chunks_exact(4)) {
+                let element = f32::from_ne_bytes(element.try_into().unwrap());
+                assert!(
+                    (value - element).abs() <= max * tolerance,
+                    "{} restored as {} at {} precision",
+                    value,
+                    element,
+                    precision
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_downcast_skips_other_tensors() -> Result<()> {
+        let tensor = Tensor::new(DType::BF16, vec![4], vec![0; 8])?;
+        assert!(Precision::FP8.downcast(&tensor)?.is_none());
+        let tensor = Tensor::new(DType::F32, vec![4], vec![0; 16])?;
+        assert!(Precision::Full.downcast(&tensor)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_downcast_messages() -> Result<()> {
+        let tensor = Tensor::new(DType::F32, vec![16], vec![0; 64])?;
+        let msg = Precision::BF16.downcast(&tensor)?.unwrap();
+        assert!(
+            Precision::BF16
+                .restored_size(&msg[..msg.len() - 1])
+                .is_err()
+        );
+        assert!(Precision::FP8.restored_size(&msg).is_err());
+        let mut buffer = vec![0; Precision::BF16.restored_size(&msg)? + 1];
+        assert!(Precision::BF16.restore_into(&msg, &mut buffer).is_err());
+
+        // Shapes that overflow are rejected before their layout is checked
+        let huge = Tensor {
+            shape: vec![1 << 40, 1 << 40, 0],
+            strides: vec![4; 3],
+            byte_order: ByteOrder::Little,
+            ..tensor
+        };
+        assert!(Precision::BF16.restored_size(&huge.encode()?).is_err());
+        assert!(Precision::BF16.downcast(&huge).is_err());
+
+        for precision in [
+            Precision::Full,
+            Precision::BF16,
+            Precision::F16,
+            Precision::FP8,
+        ] {
+            assert_eq!(precision.to_string().parse::<Precision>()?, precision);
+            assert_eq!(Precision::from_code(precision.code())?, precision);
+        }
+        assert!("fp4".parse::<Precision>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_e4m3() {
+        assert_eq!(f32_to_e4m3(1.0), 0x38);
+        assert_eq!(f32_to_e4m3(-2.0), 0xc0);
+        assert_eq!(f32_to_e4m3(448.0), 0x7e);
+        assert_eq!(f32_to_e4m3(1e6), 0x7e);
+        assert_eq!(f32_to_e4m3(2f32.powi(-9)), 0x01);
+        assert_eq!(f32_to_e4m3(1.0625), 0x38);
+        assert!(e4m3_to_f32(f32_to_e4m3(f32::NAN)).is_nan());
+
+        // Every finite value maps back to its own code
+        for code in (0..=255u8).filter(|code| code & 0x7f != 0x7f) {
+            assert_eq!(f32_to_e4m3(e4m3_to_f32(code)), code);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use tokio::sync::futures::Notified;
+use tokio::sync::oneshot::error::TryRecvError;
+use tokio::sync::{Notify, oneshot};
+
+/// Hands out turns in program order per key, e.g. per peer and tag, so that works
+/// spawned as independent tasks still access a stream in the order they were issued
+#[derive(Debug)]
+pub(crate) struct TurnQueue<K> {
+    // Completion signal of the most recently issued turn of each key
+    last: Arc<Mutex<HashMap<K, oneshot::Receiver<Handover>>>>,
+    // Notified whenever a turn of any key ends
+    ended: Arc<Notify>,
+}
+
+// Completion signal of the previous turn, which a turn hands over to the next one if it
+// ends before the previous turn did
+#[derive(Debug)]
+struct Handover(oneshot::Receiver<Handover>);
+
+impl<K> Clone for TurnQueue<K> {
+    fn clone(&self) -> Self {
+        Self {
+            last: self.last.clone(),
+            ended: self.ended.clone(),
+        }
+    }
+}
+
+impl<K> Default for TurnQueue<K> {
+    fn default() -> Self {
+        Self {
+            last: Arc::new(Mutex::new(HashMap::new())),
+            ended: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> TurnQueue<K> {
+    /// Issue the next turn of a key. Has to be called synchronously when the work is
+    /// issued, not from within the spawned task
+    pub fn push(&self, key: K) -> Turn {
+        let (done, next) = oneshot::channel();
+        let previous = self.last.lock().unwrap().insert(key, next);
+        Turn {
+            previous,
+            done: Some(done),
+            ended: self.ended.clone(),
+        }
+    }
+
+    /// Issue the next turn of a key only if all earlier turns of the key ended, e.g. to
+    /// claim a stream without jumping ahead of works that are waiting for it
+    pub fn try_push(&self, key: K) -> Option<Turn> {
+        let mut last = self.last.lock().unwrap();
+        if !Self::ended(&mut last, &key) {
+            return None;
+        }
+        let (done, next) = oneshot::channel();
+        last.insert(key, next);
+        Some(Turn {
+            previous: None,
+            done: Some(done),
+            ended: self.ended.clone(),
+        })
+    }
+
+    /// Future that completes once a turn of any key ended, e.g. to retry
+    /// [`TurnQueue::try_push`]. Only turns that end after it was polled (or enabled)
+    /// count
+    pub fn turn_ended(&self) -> Notified<'_> {
+        self.ended.notified()
+    }
+
+    /// Whether all turns of a key ended, i.e. no work is waiting for the key
+    pub fn is_idle(&self, key: &K) -> bool {
+        Self::ended(&mut self.last.lock().unwrap(), key)
+    }
+
+    fn ended(last: &mut HashMap<K, oneshot::Receiver<Handover>>, key: &K) -> bool {
+        while let Some(previous) = last.get_mut(key) {
+            match previous.try_recv() {
+                // Turn ended before its predecessor, which may still be running
+                Ok(Handover(earlier)) => *previous = earlier,
+                Err(TryRecvError::Empty) => return false,
+                // Forget the signal of ended turns, which cannot be awaited once it was
+                // received
+                Err(TryRecvError::Closed) => {
+                    last.remove(key);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Turn of a single work, which ends (and lets the next turn of the same key start)
+/// when it is dropped
+#[derive(Debug)]
+pub(crate) struct Turn {
+    previous: Option<oneshot::Receiver<Handover>>,
+    done: Option<oneshot::Sender<Handover>>,
+    ended: Arc<Notify>,
+}
+
+impl Turn {
+    /// Wait until all earlier turns of the same key ended
+    pub async fn wait(&mut self) {
+        while let Some(previous) = &mut self.previous {
+            // Errors only signal that the previous turn ended, a handover that it ended
+            // before its own predecessor
+            self.previous = previous.await.ok().map(|Handover(earlier)| earlier);
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        // Works that fail before their turn (e.g. a send whose compression failed) must not
+        // let later turns jump ahead of the earlier ones
+        if let (Some(previous), Some(done)) = (self.previous.take(), self.done.take()) {
+            let _ = done.send(Handover(previous));
+        }
+        drop(self.done.take());
+        self.ended.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_turns_in_program_order() {
+        let runtime = Runtime::new().unwrap();
+        let queue = TurnQueue::default();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Earlier turns take longer, but still end first
+        let handles: Vec<_> = (0..5u64)
+            .map(|i| {
+                let mut turn = queue.push(0);
+                let order = order.clone();
+                runtime.spawn(async move {
+                    tokio::time::sleep(Duration::from_millis((5 - i) * 10)).await;
+                    turn.wait().await;
+                    order.lock().unwrap().push(i);
+                })
+            })
+            .collect();
+        for handle in handles {
+            runtime.block_on(handle).unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_turns_of_other_keys_are_independent() {
+        let runtime = Runtime::new().unwrap();
+        let queue = TurnQueue::default();
+        let _blocking = queue.push(0);
+        let mut turn = queue.push(1);
+        runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(1), turn.wait())
+                .await
+                .unwrap()
+        });
+    }
+
+    #[test]
+    fn test_turn_ending_early_keeps_later_turns_waiting() {
+        let runtime = Runtime::new().unwrap();
+        let queue = TurnQueue::default();
+        let first = queue.push(0);
+        let failed = queue.push(0);
+        let mut last = queue.push(0);
+
+        // Second work fails before its turn, while the first one is still running
+        drop(failed);
+        runtime.block_on(async {
+            let early = tokio::time::timeout(Duration::from_millis(50), last.wait()).await;
+            assert!(early.is_err());
+            assert!(!queue.is_idle(&0));
+            drop(first);
+            tokio::time::timeout(Duration::from_secs(1), last.wait())
+                .await
+                .unwrap()
+        });
+        drop(last);
+        assert!(queue.is_idle(&0));
+    }
+
+    #[test]
+    fn test_try_push_only_when_idle() {
+        let queue = TurnQueue::default();
+        assert!(queue.is_idle(&0));
+        let turn = queue.try_push(0).unwrap();
+        assert!(!queue.is_idle(&0));
+        assert!(queue.try_push(0).is_none());
+
+        // Other keys are not affected, and the key is idle again once the turn ended
+        assert!(queue.is_idle(&1));
+        drop(turn);
+        assert!(queue.is_idle(&0));
+        let turn = queue.try_push(0).unwrap();
+        assert!(queue.try_push(0).is_none());
+
+        // Turns issued after checking an idle key don't wait
+        let runtime = Runtime::new().unwrap();
+        drop(turn);
+        assert!(queue.is_idle(&0));
+        let mut turn = queue.push(0);
+        runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(1), turn.wait())
+                .await
+                .unwrap()
+        });
+
+        // Instead of polling, claims wait for the turns of the key to end
+        let mut ended = std::pin::pin!(queue.turn_ended());
+        ended.as_mut().enable();
+        assert!(queue.try_push(0).is_none());
+        drop(turn);
+        runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(1), ended)
+                .await
+                .unwrap()
+        });
+        assert!(queue.try_push(0).is_some());
+    }
+}
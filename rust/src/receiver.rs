@@ -1,64 +1,640 @@
-use anyhow::{Error, Result, ensure};
+use anyhow::{Error, Result, anyhow, ensure};
 use iroh::protocol::{ProtocolHandler, Router};
 use iroh::{
-    Endpoint,
+    Endpoint, NodeId,
     endpoint::{Connection, RecvStream},
 };
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::future::poll_fn;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll, Waker};
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard};
+use tokio::task::JoinSet;
 
+use crate::auth::verify_cluster_proof;
+use crate::error::{
+    ChecksumMismatch, DuplicateMessage, MessageSizeMismatch, MessageTooLarge, map_connection_reset,
+};
+use crate::frame::{Checksum, Codec, FRAME_HEADER_LEN, FrameHeader};
+use crate::handshake::{Handshake, TagHeader};
+use crate::node::NodeConfig;
+use crate::precision::Precision;
+use crate::queue::{Turn, TurnQueue};
+use crate::tensor::{Tensor, decode_tensors};
 use crate::work::RecvWork;
 
-const ALPN: &[u8] = b"prime-iroh";
+pub(crate) const ALPN: &[u8] = b"prime-iroh";
+
+// Close code for connections superseded by a new connection from the same peer
+const REPLACED_CODE: u32 = 1;
+
+// Close code for connections from peers that are not allowed to connect
+pub(crate) const REJECTED_CODE: u32 = 2;
+
+// Close code for connections from peers that failed to prove knowledge of the cluster token
+pub(crate) const UNAUTHORIZED_CODE: u32 = 3;
+
+// Close code for connections with an invalid or incompatible handshake
+pub(crate) const HANDSHAKE_CODE: u32 = 4;
+
+// Stop code for streams on which a message exceeded the maximum message size
+pub(crate) const MESSAGE_TOO_LARGE_CODE: u32 = 1;
+
+// Stop code for streams on which a message had an invalid frame header
+pub(crate) const INVALID_FRAME_CODE: u32 = 3;
+
+// Size of the chunks in which skipped messages are read
+const SKIP_CHUNK_LEN: usize = 64 * 1024;
+
+// Read the body of a message into a buffer of its uncompressed size, verifying its
+// checksum and decompressing it (off the runtime threads) if needed
+async fn read_frame<B: Buffer>(
+    mut stream: OwnedMutexGuard<TagStream>,
+    header: FrameHeader,
+    size: usize,
+    mut msg: B,
+    mismatch: ChecksumMismatch,
+) -> Result<B> {
+    let compressed = match header.codec {
+        Codec::None => {
+            stream.read_exact(msg.as_mut()).await?;
+            None
+        }
+        _ => {
+            let mut compressed = vec![0; size];
+            stream.read_exact(&mut compressed).await?;
+            Some(compressed)
+        }
+    };
+    let mut trailer = vec![0; header.checksum.len()];
+    stream.read_exact(&mut trailer).await?;
+    drop(stream);
+    if header.codec == Codec::None && header.checksum == Checksum::None {
+        return Ok(msg);
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let body: &[u8] = match &compressed {
+            Some(compressed) => compressed,
+            None => msg.as_mut(),
+        };
+        if !header.checksum.verify(body, &trailer) {
+            return Err(mismatch.into());
+        }
+        if let Some(compressed) = compressed {
+            header.codec.decompress_into(&compressed, msg.as_mut())?;
+        }
+        Ok(msg)
+    })
+    .await?
+}
+
+/// Buffer that a message can be received into without copying it, e.g. `Vec<u8>`,
+/// `Box<[u8]>` or a static mutable byte slice. It is held until the receive completes
+pub trait Buffer: AsMut<[u8]> + Send + 'static {}
+
+impl<T: AsMut<[u8]> + Send + 'static> Buffer for T {}
+
+// Receive stream of a tag, together with the part of the next frame header that was
+// already read (e.g. by a probe, which must not consume the message)
+#[derive(Debug)]
+struct TagStream {
+    stream: RecvStream,
+    header: Vec<u8>,
+    // Waker of the receive of any tag waiting for the next header without holding the
+    // stream, woken by whoever completes the header (since streams only remember the
+    // waker of their last read)
+    waker: Option<Waker>,
+}
+
+impl TagStream {
+    fn new(stream: RecvStream) -> Self {
+        Self {
+            stream,
+            header: Vec::with_capacity(FRAME_HEADER_LEN),
+            waker: None,
+        }
+    }
+
+    // Read and drop the given number of bytes, e.g. the body of a message that cannot be
+    // received
+    async fn skip(&mut self, mut len: usize) -> Result<()> {
+        let mut chunk = vec![0; len.min(SKIP_CHUNK_LEN)];
+        while len > 0 {
+            let n = len.min(chunk.len());
+            self.stream.read_exact(&mut chunk[..n]).await?;
+            len -= n;
+        }
+        Ok(())
+    }
+
+    // Read the header of the next message without consuming it. Cancel-safe, since the
+    // bytes read so far are kept for the next call
+    async fn peek_header(&mut self) -> Result<[u8; FRAME_HEADER_LEN]> {
+        let header = self.fill_header().await;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        header
+    }
+
+    async fn fill_header(&mut self) -> Result<[u8; FRAME_HEADER_LEN]> {
+        while self.header.len() < FRAME_HEADER_LEN {
+            let mut chunk = [0; FRAME_HEADER_LEN];
+            let missing = FRAME_HEADER_LEN - self.header.len();
+            let len = self
+                .stream
+                .read(&mut chunk[..missing])
+                .await?
+                .ok_or_else(|| anyhow!("Stream finished before the next message"))?;
+            self.header.extend_from_slice(&chunk[..len]);
+        }
+        Ok(self.header[..].try_into().unwrap())
+    }
+
+    // Poll for the header of the next message, waking the task of the context once it
+    // arrived (or the stream failed), even if others poll the stream in between
+    fn poll_header(&mut self, cx: &mut Context<'_>) -> Poll<Result<[u8; FRAME_HEADER_LEN]>> {
+        self.waker = Some(cx.waker().clone());
+        std::pin::pin!(self.peek_header()).poll(cx)
+    }
+
+    // Peek at the header of the next message if it already arrived, without waiting. The
+    // stream keeps waking the task waiting for the header, if any
+    fn try_peek_header(&mut self) -> Result<Option<[u8; FRAME_HEADER_LEN]>> {
+        let waker = self.waker.clone();
+        let waker = waker.as_ref().unwrap_or(Waker::noop());
+        let mut peek = std::pin::pin!(self.peek_header());
+        match peek.as_mut().poll(&mut Context::from_waker(waker)) {
+            Poll::Ready(header) => header.map(Some),
+            Poll::Pending => Ok(None),
+        }
+    }
+
+    // Read the header of the next message, whose body follows on the stream
+    async fn read_header(&mut self) -> Result<[u8; FRAME_HEADER_LEN]> {
+        let header = self.peek_header().await?;
+        self.header.clear();
+        Ok(header)
+    }
+}
+
+impl Deref for TagStream {
+    type Target = RecvStream;
+
+    fn deref(&self) -> &RecvStream {
+        &self.stream
+    }
+}
+
+impl DerefMut for TagStream {
+    fn deref_mut(&mut self) -> &mut RecvStream {
+        &mut self.stream
+    }
+}
+
+// Size of a message once received (i.e. of the restored tensor for downcast tensors),
+// from the header of its frame
+fn probed_size(header: &[u8; FRAME_HEADER_LEN]) -> Result<usize> {
+    Ok(FrameHeader::decode(header)?.restored_size.try_into()?)
+}
+
+// Wait for the header of the next message of a stream and return the locked stream. The
+// stream is only locked while polling its header, so that probes of the tag don't find
+// it locked while no message arrived
+async fn wait_for_header(stream: &Arc<Mutex<TagStream>>) -> Result<OwnedMutexGuard<TagStream>> {
+    loop {
+        let mut guard = stream.clone().lock_owned().await;
+        if let Poll::Ready(header) = poll_fn(|cx| Poll::Ready(guard.poll_header(cx))).await {
+            header?;
+            return Ok(guard);
+        }
+        // Wait for the wakeup of the poll without holding the stream
+        drop(guard);
+        let mut woken = false;
+        poll_fn(|_| match std::mem::replace(&mut woken, true) {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        })
+        .await;
+    }
+}
+
+type RecvStreams = Arc<Mutex<HashMap<usize, Arc<Mutex<TagStream>>>>>;
 
 #[derive(Clone, Debug)]
 struct MultiStreamConnection {
     connection: Connection,
-    recv_streams: Vec<Arc<Mutex<RecvStream>>>,
+    recv_streams: RecvStreams,
+    // Notified whenever the sender opens the stream of a new tag
+    new_stream: Arc<Notify>,
+    // Sequence number of the next message expected via each tag, shared with the
+    // connections that replace this one (since senders keep numbering across reconnects)
+    next_seqs: Arc<Mutex<HashMap<usize, u64>>>,
+}
+
+impl MultiStreamConnection {
+    // Get the stream of a tag, waiting for the sender to open it if necessary
+    async fn wait_for_stream(&self, tag: usize) -> Result<Arc<Mutex<TagStream>>> {
+        loop {
+            let mut notified = std::pin::pin!(self.new_stream.notified());
+            notified.as_mut().enable();
+            if let Some(stream) = self.recv_streams.lock().await.get(&tag) {
+                return Ok(stream.clone());
+            }
+            tokio::select! {
+                _ = notified => {}
+                reason = self.connection.closed() => return Err(reason.into()),
+            }
+        }
+    }
+
+    // Wait for the next message of any tag that no other receive is waiting for, and
+    // claim it with a turn that keeps later receives of the tag waiting until it is read
+    async fn wait_for_any_message(
+        &self,
+        peer_id: NodeId,
+        recv_turns: &TurnQueue<(NodeId, usize)>,
+    ) -> Result<(usize, OwnedMutexGuard<TagStream>, Turn)> {
+        loop {
+            let mut notified = std::pin::pin!(self.new_stream.notified());
+            notified.as_mut().enable();
+
+            // Race the streams of all tags opened so far
+            let mut peeks = JoinSet::new();
+            for (&tag, stream) in self.recv_streams.lock().await.iter() {
+                let stream = stream.clone();
+                let recv_turns = recv_turns.clone();
+                peeks.spawn(async move {
+                    loop {
+                        let guard = wait_for_header(&stream).await?;
+                        let mut ended = std::pin::pin!(recv_turns.turn_ended());
+                        ended.as_mut().enable();
+                        if let Some(turn) = recv_turns.try_push((peer_id, tag)) {
+                            return Ok::<_, Error>((tag, guard, turn));
+                        }
+                        // Leave the message to the receive that is waiting for it, and try
+                        // again once a receive ended
+                        drop(guard);
+                        ended.await;
+                    }
+                });
+            }
+            loop {
+                tokio::select! {
+                    Some(peek) = peeks.join_next() => match peek? {
+                        Ok(claimed) => return Ok(claimed),
+                        // Streams that were reset are raced again once their tag reopens them
+                        Err(e) => log::debug!("Stopped waiting for stream: {}", e),
+                    },
+                    _ = &mut notified => break,
+                    reason = self.connection.closed() => return Err(reason.into()),
+                }
+            }
+        }
+    }
+
+    // Check the sequence number of the next message of a tag, returning the expected
+    // sequence number if the message is a duplicate (or arrived out of order). Gaps are
+    // only logged, since later messages are valid even if earlier ones were lost
+    async fn check_seq(&self, peer_id: NodeId, tag: usize, seq: u64) -> Option<u64> {
+        let mut next_seqs = self.next_seqs.lock().await;
+        let expected = next_seqs.get(&tag).copied().unwrap_or_default();
+        if seq < expected {
+            return Some(expected);
+        }
+        if seq > expected {
+            log::warn!(
+                "Missing {} message(s) via stream {} of peer {} before message {}",
+                seq - expected,
+                tag,
+                peer_id.fmt_short(),
+                seq
+            );
+        }
+        next_seqs.insert(tag, seq + 1);
+        None
+    }
+
+    // Forget the (reset) stream of a tag, unless it was already replaced. The sender
+    // opens a new stream with the next message of the tag
+    async fn forget_stream(&self, tag: usize, stream: &Arc<Mutex<TagStream>>) {
+        let mut recv_streams = self.recv_streams.lock().await;
+        if recv_streams
+            .get(&tag)
+            .is_some_and(|current| Arc::ptr_eq(current, stream))
+        {
+            recv_streams.remove(&tag);
+        }
+    }
+
+    // Stop the stream of a tag (e.g. in the middle of a message that cannot be received)
+    // and forget it
+    async fn stop_stream(
+        &self,
+        tag: usize,
+        mut guard: OwnedMutexGuard<TagStream>,
+        code: u32,
+    ) -> Result<()> {
+        let stream = OwnedMutexGuard::mutex(&guard).clone();
+        guard.stop(code.into())?;
+        drop(guard);
+        self.forget_stream(tag, &stream).await;
+        Ok(())
+    }
+
+    // Read the next message of a locked stream into the buffer returned for its size,
+    // which has to match the expected size (if any). Returns the message together with
+    // its sequence number
+    async fn read_message<B: Buffer>(
+        &self,
+        peer_id: NodeId,
+        tag: usize,
+        mut guard: OwnedMutexGuard<TagStream>,
+        max_message_size: Option<usize>,
+        expected_size: Option<usize>,
+        buffer: impl FnOnce(usize) -> B + Send + 'static,
+    ) -> Result<(B, u64)> {
+        // Read the header of the message
+        let header = guard.read_header().await?;
+        let header = match FrameHeader::decode(&header) {
+            Ok(header) => header,
+            Err(e) => {
+                self.stop_stream(tag, guard, INVALID_FRAME_CODE).await?;
+                return Err(e);
+            }
+        };
+        let seq = header.seq;
+        let expected_seq = self.check_seq(peer_id, tag, seq).await;
+
+        // Reset only this stream instead of allocating an oversized message (or one that
+        // does not even fit into memory), before and after decompression and restoring
+        let max_size = max_message_size.unwrap_or(usize::MAX);
+        let fits = |size: u64| usize::try_from(size).ok().filter(|&size| size <= max_size);
+        let (Some(size), Some(raw_size), Some(restored_size)) = (
+            fits(header.size),
+            fits(header.raw_size),
+            fits(header.restored_size),
+        ) else {
+            self.stop_stream(tag, guard, MESSAGE_TOO_LARGE_CODE).await?;
+            return Err(MessageTooLarge {
+                peer_id,
+                tag,
+                size: header.size.max(header.raw_size).max(header.restored_size),
+                max_size,
+            }
+            .into());
+        };
+
+        // Drop messages that don't fit the buffer after reading them like duplicates, so
+        // that the stream stays usable
+        if let Some(buffer_size) = expected_size
+            && restored_size != buffer_size
+        {
+            let Some(len) = size.checked_add(header.checksum.len()) else {
+                self.stop_stream(tag, guard, MESSAGE_TOO_LARGE_CODE).await?;
+                return Err(MessageTooLarge {
+                    peer_id,
+                    tag,
+                    size: header.size,
+                    max_size,
+                }
+                .into());
+            };
+            guard.skip(len).await?;
+            return Err(MessageSizeMismatch {
+                peer_id,
+                tag,
+                size: restored_size,
+                buffer_size,
+            }
+            .into());
+        }
+
+        let mismatch = ChecksumMismatch { peer_id, tag, seq };
+        let msg = if header.precision == Precision::Full {
+            read_frame(guard, header, size, buffer(raw_size), mismatch).await?
+        } else {
+            // Restore downcast tensors (off the runtime threads) into the buffer
+            let msg = read_frame(guard, header, size, vec![0; raw_size], mismatch).await?;
+            tokio::task::spawn_blocking(move || {
+                let size = header.precision.restored_size(&msg)?;
+                ensure!(
+                    size == restored_size,
+                    "Downcast tensor restores to {} bytes, but its header announced {} bytes",
+                    size,
+                    restored_size
+                );
+                let mut buffer = buffer(size);
+                header.precision.restore_into(&msg, buffer.as_mut())?;
+                Ok::<_, Error>(buffer)
+            })
+            .await??
+        };
+
+        // Drop duplicates only after reading them, so that the stream stays usable
+        if let Some(expected) = expected_seq {
+            return Err(DuplicateMessage {
+                peer_id,
+                tag,
+                seq,
+                expected,
+            }
+            .into());
+        }
+        Ok((msg, seq))
+    }
+
+    // Accept the streams of lazily opened tags until the connection is closed
+    async fn accept_tag_streams(self, peer_id: NodeId) {
+        while let Ok(mut stream) = self.connection.accept_uni().await {
+            let result = async {
+                let tag = TagHeader::read(&mut stream).await?.tag()?;
+                let mut recv_streams = self.recv_streams.lock().await;
+                ensure!(
+                    !recv_streams.contains_key(&tag),
+                    "Stream of tag {} opened twice",
+                    tag
+                );
+                recv_streams.insert(tag, Arc::new(Mutex::new(TagStream::new(stream))));
+                Ok(tag)
+            }
+            .await;
+            match result {
+                Ok(tag) => {
+                    log::debug!(
+                        "Accepted stream for tag {} from {}",
+                        tag,
+                        peer_id.fmt_short()
+                    );
+                    self.new_stream.notify_waiters();
+                }
+                Err(e) => {
+                    reject(&self.connection, &peer_id, HANDSHAKE_CODE, e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+type Connections = Arc<Mutex<HashMap<NodeId, MultiStreamConnection>>>;
+
+// Get the connection to a peer
+async fn get_connection(
+    connections: &Connections,
+    peer_id: NodeId,
+) -> Result<MultiStreamConnection> {
+    connections
+        .lock()
+        .await
+        .get(&peer_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("No connection from peer {}", peer_id.fmt_short()))
 }
 
 #[derive(Clone, Debug)]
 struct ReceiverHandler {
-    connection: Arc<Mutex<Option<MultiStreamConnection>>>,
-    num_streams: usize,
+    node_id: NodeId,
+    connections: Connections,
+    max_streams: usize,
+    auto_reconnect: bool,
+    allowed_peers: Option<Arc<HashSet<NodeId>>>,
+    cluster_token: Option<Arc<[u8]>>,
+    max_message_size: Option<usize>,
 }
 
 impl ReceiverHandler {
-    fn new(num_streams: usize, connection: Arc<Mutex<Option<MultiStreamConnection>>>) -> Self {
+    fn new(node_id: NodeId, config: &NodeConfig, connections: Connections) -> Self {
         Self {
-            connection,
-            num_streams,
+            node_id,
+            connections,
+            max_streams: config.max_recv_streams.unwrap_or(config.num_streams),
+            auto_reconnect: config.auto_reconnect,
+            allowed_peers: config.allowed_peers.clone().map(Arc::new),
+            cluster_token: config.cluster_token.as_deref().map(Arc::from),
+            max_message_size: config.max_message_size,
         }
     }
 }
 
+// Close a connection that failed the handshake, telling the sender why
+fn reject(conn: &Connection, peer_id: &NodeId, code: u32, e: Error) -> Error {
+    log::warn!("Rejected connection from {}: {}", peer_id.fmt_short(), e);
+    conn.close(code.into(), e.to_string().as_bytes());
+    e
+}
+
+// A new connection from a peer we are already connected to is only accepted if it
+// replaces a lost connection (or any connection, if auto-reconnect is enabled)
+fn ensure_replaceable(
+    connections: &HashMap<NodeId, MultiStreamConnection>,
+    peer_id: &NodeId,
+    auto_reconnect: bool,
+) -> Result<()> {
+    if let Some(existing) = connections.get(peer_id) {
+        ensure!(
+            auto_reconnect || existing.connection.close_reason().is_some(),
+            "Already have a connection from peer {}",
+            peer_id.fmt_short()
+        );
+    }
+    Ok(())
+}
+
 impl ProtocolHandler for ReceiverHandler {
     fn accept(
         &self,
         conn: Connection,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>> {
-        let num_streams = self.num_streams;
-        let connection = self.connection.clone();
+        let max_streams = self.max_streams;
+        let auto_reconnect = self.auto_reconnect;
+        let allowed_peers = self.allowed_peers.clone();
+        let cluster_token = self.cluster_token.clone();
+        let node_id = self.node_id;
+        let max_message_size = self.max_message_size;
+        let connections = self.connections.clone();
         Box::pin(async move {
-            let mut connection = connection.lock().await;
-            ensure!(connection.is_none(), "Already have a connection");
+            let peer_id = conn.remote_node_id()?;
+
+            // Reject unknown peers before accepting any streams
+            if let Some(allowed_peers) = allowed_peers
+                && !allowed_peers.contains(&peer_id)
+            {
+                let e = anyhow!("Sender is not an allowed peer");
+                return Err(reject(&conn, &peer_id, REJECTED_CODE, e));
+            }
+
+            ensure_replaceable(&*connections.lock().await, &peer_id, auto_reconnect)
+                .map_err(|e| reject(&conn, &peer_id, REJECTED_CODE, e))?;
 
-            // Initialize receive streams
-            let mut streams = Vec::with_capacity(num_streams);
-            for _ in 0..num_streams {
+            // Initialize receive streams, each opened by a handshake. The first handshake
+            // determines the number of streams
+            let mut streams = Vec::new();
+            let mut num_streams = 1;
+            while streams.len() < num_streams {
+                let stream_index = streams.len();
                 let mut recv_stream = conn.accept_uni().await?;
-                let mut buffer = [0; 4]; // Buffer to hold the 0u32 value
-                recv_stream.read_exact(&mut buffer).await?;
-                streams.push(Arc::new(Mutex::new(recv_stream)));
+                let handshake = Handshake::read(&mut recv_stream)
+                    .await
+                    .and_then(|handshake| {
+                        if stream_index == 0 {
+                            num_streams = handshake.negotiate(max_streams)?;
+                        }
+                        handshake.validate(stream_index, num_streams)?;
+                        Ok(handshake)
+                    })
+                    .map_err(|e| reject(&conn, &peer_id, HANDSHAKE_CODE, e))?;
+
+                // Check the proof of the cluster token (which has to be empty if there is none)
+                let authorized = match &cluster_token {
+                    Some(token) => {
+                        verify_cluster_proof(token, &peer_id, &node_id, &handshake.proof)
+                    }
+                    None => handshake.proof.is_empty(),
+                };
+                if !authorized {
+                    let e = anyhow!("Invalid cluster token");
+                    return Err(reject(&conn, &peer_id, UNAUTHORIZED_CODE, e));
+                }
+                streams.push(Arc::new(Mutex::new(TagStream::new(recv_stream))));
             }
 
             // Store connection and streams
+            let mut connections = connections.lock().await;
+            ensure_replaceable(&connections, &peer_id, auto_reconnect)
+                .map_err(|e| reject(&conn, &peer_id, REJECTED_CODE, e))?;
             let connection_ref = MultiStreamConnection {
-                connection: conn,
-                recv_streams: streams,
+                connection: conn.clone(),
+                recv_streams: Arc::new(Mutex::new(streams.into_iter().enumerate().collect())),
+                new_stream: Arc::new(Notify::new()),
+                // Replacement connections continue the sequence numbers of the lost one
+                next_seqs: connections
+                    .get(&peer_id)
+                    .map(|replaced| replaced.next_seqs.clone())
+                    .unwrap_or_default(),
+            };
+            tokio::spawn(connection_ref.clone().accept_tag_streams(peer_id));
+            if let Some(replaced) = connections.insert(peer_id, connection_ref) {
+                // Fail works still waiting on the old connection
+                replaced.connection.close(REPLACED_CODE.into(), b"replaced");
+                log::info!("Replaced connection from {}", peer_id.fmt_short());
+            } else {
+                log::info!("Accepted connection from {}", peer_id.fmt_short());
+            }
+            drop(connections);
+
+            // Acknowledge the handshake, which completes the connect of the sender and
+            // tells it which messages we accept
+            let mut ack_stream = conn.open_uni().await?;
+            let ack = Handshake {
+                max_message_size: max_message_size.map(|size| size as u64),
+                ..Handshake::new(0, num_streams, Vec::new())?
             };
-            *connection = Some(connection_ref);
+            ack.write(&mut ack_stream).await?;
+            ack_stream.finish()?;
 
             Ok(())
         })
@@ -69,14 +645,20 @@ pub struct Receiver {
     runtime: Arc<Runtime>,
     endpoint: Endpoint,
     router: Router,
-    connection: Arc<Mutex<Option<MultiStreamConnection>>>,
+    connections: Connections,
+    recv_turns: TurnQueue<(NodeId, usize)>,
+    max_message_size: Option<usize>,
 }
 
 impl Receiver {
     pub fn new(runtime: Arc<Runtime>, endpoint: Endpoint, num_streams: usize) -> Self {
+        Self::with_config(runtime, endpoint, &NodeConfig::new(num_streams))
+    }
+
+    pub fn with_config(runtime: Arc<Runtime>, endpoint: Endpoint, config: &NodeConfig) -> Self {
         log::info!("Creating receiver (ID={})", endpoint.node_id().fmt_short());
-        let connection = Arc::new(Mutex::new(None));
-        let handler = ReceiverHandler::new(num_streams, connection.clone());
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let handler = ReceiverHandler::new(endpoint.node_id(), config, connections.clone());
         let router = runtime.block_on(async {
             Router::builder(endpoint.clone())
                 .accept(ALPN, handler)
@@ -89,53 +671,223 @@ impl Receiver {
             runtime,
             endpoint,
             router,
-            connection,
+            connections,
+            recv_turns: TurnQueue::default(),
+            max_message_size: config.max_message_size,
         }
     }
 
     pub fn is_ready(&self) -> bool {
         self.runtime.block_on(async {
-            let connection = self.connection.lock().await;
-            connection.is_some()
+            let connections = self.connections.lock().await;
+            !connections.is_empty()
         })
     }
 
-    pub fn irecv(&mut self, tag: usize) -> Result<RecvWork> {
-        // Ensure we have a connection
+    pub fn peers(&self) -> Vec<NodeId> {
+        self.runtime.block_on(async {
+            let connections = self.connections.lock().await;
+            connections.keys().copied().collect()
+        })
+    }
+
+    // Get the only peer, for receives that don't specify one
+    fn single_peer(&self) -> Result<NodeId> {
+        // Ensure we have exactly one connection
         ensure!(self.is_ready(), "Receiver is not ready");
-        log::debug!("Receiving message via stream {}", tag);
+        let peers = self.peers();
+        ensure!(
+            peers.len() == 1,
+            "Receiver has {} connections, specify a peer to receive from",
+            peers.len()
+        );
+        Ok(peers[0])
+    }
 
-        let connection = self.connection.clone();
-        let handle = self.runtime.spawn(async move {
-            // Get the optional state
-            let connection = connection.lock().await;
-            ensure!(connection.is_some(), "No connection available");
+    pub fn irecv(&mut self, tag: usize) -> Result<RecvWork> {
+        self.irecv_from(self.single_peer()?, tag)
+    }
+
+    pub fn irecv_from(&mut self, peer_id: NodeId, tag: usize) -> Result<RecvWork> {
+        self.irecv_with(peer_id, tag, None, |size| vec![0; size])
+    }
 
-            // Unwrap the receiver state
-            let connection = connection.as_ref().unwrap();
-            ensure!(tag < connection.recv_streams.len(), "Invalid tag");
+    pub fn irecv_into<B: Buffer>(&mut self, tag: usize, buffer: B) -> Result<RecvWork<B>> {
+        self.irecv_from_into(self.single_peer()?, tag, buffer)
+    }
+
+    /// Receive a message into a buffer of exactly its size, failing with a
+    /// [`MessageSizeMismatch`] otherwise
+    pub fn irecv_from_into<B: Buffer>(
+        &mut self,
+        peer_id: NodeId,
+        tag: usize,
+        mut buffer: B,
+    ) -> Result<RecvWork<B>> {
+        let buffer_size = buffer.as_mut().len();
+        self.irecv_with(peer_id, tag, Some(buffer_size), move |_| buffer)
+    }
+
+    // Receive a message into the buffer returned for its size, which has to match the
+    // expected size (if any)
+    fn irecv_with<B: Buffer>(
+        &mut self,
+        peer_id: NodeId,
+        tag: usize,
+        expected_size: Option<usize>,
+        buffer: impl FnOnce(usize) -> B + Send + 'static,
+    ) -> Result<RecvWork<B>> {
+        log::debug!(
+            "Receiving message from {} via stream {}",
+            peer_id.fmt_short(),
+            tag
+        );
+
+        // Messages of the same peer and tag are read in the order they were issued
+        let mut turn = self.recv_turns.push((peer_id, tag));
+
+        let connections = self.connections.clone();
+        let max_message_size = self.max_message_size;
+        let recv_seq = Arc::new(OnceLock::new());
+        let seq_cell = recv_seq.clone();
+        let handle = self.runtime.spawn(async move {
+            turn.wait().await;
 
-            // Get the stream
-            let stream = connection.recv_streams[tag].clone();
-            let mut stream = stream.lock().await;
+            let connection = get_connection(&connections, peer_id).await?;
+            let result = async {
+                // Get and lock the stream, which the sender opens on first use of the tag
+                let stream = connection.wait_for_stream(tag).await?;
+                let guard = stream.lock_owned().await;
+                let (msg, seq) = connection
+                    .read_message(peer_id, tag, guard, max_message_size, expected_size, buffer)
+                    .await?;
+                seq_cell.set(seq).ok();
+                Ok(msg)
+            }
+            .await;
+            result.map_err(|e| map_connection_reset(&connection.connection, peer_id, e))
+        });
+        Ok(RecvWork {
+            runtime: self.runtime.clone(),
+            handle,
+            seq: recv_seq,
+        })
+    }
 
-            // Read the size of the message
-            let mut size = [0; 4];
-            stream.read_exact(&mut size).await?;
-            let size = u32::from_le_bytes(size) as usize;
+    pub fn irecv_any(&mut self) -> Result<RecvWork<(usize, Vec<u8>)>> {
+        self.irecv_any_from(self.single_peer()?)
+    }
 
-            // Read the message
-            let mut msg = vec![0; size];
-            stream.read_exact(&mut msg).await?;
+    /// Receive the next message of whichever tag it arrives on first, together with the
+    /// tag. Messages of tags that other receives are waiting for are left to them
+    pub fn irecv_any_from(&mut self, peer_id: NodeId) -> Result<RecvWork<(usize, Vec<u8>)>> {
+        log::debug!(
+            "Receiving message from {} via any stream",
+            peer_id.fmt_short()
+        );
 
-            Ok(msg)
+        let connections = self.connections.clone();
+        let recv_turns = self.recv_turns.clone();
+        let max_message_size = self.max_message_size;
+        let recv_seq = Arc::new(OnceLock::new());
+        let seq_cell = recv_seq.clone();
+        let handle = self.runtime.spawn(async move {
+            let connection = get_connection(&connections, peer_id).await?;
+            let result = async {
+                let (tag, guard, _turn) = connection
+                    .wait_for_any_message(peer_id, &recv_turns)
+                    .await?;
+                let (msg, seq) = connection
+                    .read_message(peer_id, tag, guard, max_message_size, None, |size| {
+                        vec![0; size]
+                    })
+                    .await?;
+                seq_cell.set(seq).ok();
+                Ok((tag, msg))
+            }
+            .await;
+            result.map_err(|e| map_connection_reset(&connection.connection, peer_id, e))
         });
         Ok(RecvWork {
             runtime: self.runtime.clone(),
-            handle: handle,
+            handle,
+            seq: recv_seq,
+        })
+    }
+
+    pub fn iprobe(&self, tag: usize) -> Result<Option<usize>> {
+        self.iprobe_from(self.single_peer()?, tag)
+    }
+
+    /// Check whether the next message of a tag already arrived and return its size,
+    /// without receiving it. Messages that earlier receives are waiting for don't count
+    pub fn iprobe_from(&self, peer_id: NodeId, tag: usize) -> Result<Option<usize>> {
+        if !self.recv_turns.is_idle(&(peer_id, tag)) {
+            return Ok(None);
+        }
+        self.runtime.block_on(async {
+            let connection = get_connection(&self.connections, peer_id).await?;
+            let Some(stream) = connection.recv_streams.lock().await.get(&tag).cloned() else {
+                return Ok(None);
+            };
+            // Streams are locked while receives of the tag read from them, or while
+            // receives of any tag claim the message whose header just arrived
+            let Ok(mut guard) = stream.try_lock() else {
+                return Ok(None);
+            };
+            guard
+                .try_peek_header()
+                .and_then(|header| header.as_ref().map(probed_size).transpose())
+                .map_err(|e| map_connection_reset(&connection.connection, peer_id, e))
+        })
+    }
+
+    pub fn probe(&self, tag: usize) -> Result<usize> {
+        self.probe_from(self.single_peer()?, tag)
+    }
+
+    /// Wait for the next message of a tag and return its size, without receiving it. The
+    /// message is the one that the next receive of the tag returns
+    pub fn probe_from(&self, peer_id: NodeId, tag: usize) -> Result<usize> {
+        // Messages of earlier receives of the tag come first
+        let mut turn = self.recv_turns.push((peer_id, tag));
+        self.runtime.block_on(async {
+            turn.wait().await;
+            let connection = get_connection(&self.connections, peer_id).await?;
+            let result = async {
+                let stream = connection.wait_for_stream(tag).await?;
+                let header = stream.lock().await.peek_header().await?;
+                probed_size(&header)
+            }
+            .await;
+            result.map_err(|e| map_connection_reset(&connection.connection, peer_id, e))
         })
     }
 
+    pub fn irecv_tensors(&mut self, tag: usize) -> Result<RecvWork<Vec<Tensor>>> {
+        self.irecv_tensors_from(self.single_peer()?, tag)
+    }
+
+    /// Receive named tensors sent with `isend_tensors`, failing if the safetensors header
+    /// of the message is invalid or does not match its data
+    pub fn irecv_tensors_from(
+        &mut self,
+        peer_id: NodeId,
+        tag: usize,
+    ) -> Result<RecvWork<Vec<Tensor>>> {
+        Ok(self.irecv_from(peer_id, tag)?.map(move |msg| {
+            decode_tensors(&msg).map_err(|e| {
+                log::warn!(
+                    "Received invalid tensors from {} via stream {}: {}",
+                    peer_id.fmt_short(),
+                    tag,
+                    e
+                );
+                e
+            })
+        }))
+    }
+
     pub fn close(&mut self) -> Result<()> {
         if !self.is_ready() {
             log::warn!("Receiver connection does not exist, skipping close");
@@ -146,11 +898,11 @@ impl Receiver {
             self.endpoint.node_id().fmt_short()
         );
         match self.runtime.block_on(async {
-            let mut connection = self.connection.lock().await;
+            let mut connections = self.connections.lock().await;
 
-            if let Some(connection) = connection.take() {
+            for (_, connection) in connections.drain() {
                 // Close receive streams if they exist
-                for stream in &connection.recv_streams {
+                for stream in connection.recv_streams.lock().await.values() {
                     let mut stream = stream.lock().await;
                     stream.stop(0u32.into())?;
                 }
@@ -178,6 +930,16 @@ impl Receiver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::PROOF_LEN;
+    use crate::error::ConnectionReset;
+    use crate::frame::Compression;
+    use crate::handshake::PROTOCOL_VERSION;
+    use crate::precision::TensorPrecision;
+    use crate::retry::RetryPolicy;
+    use crate::tensor::{ByteOrder, DType};
+    use crate::testing::{LocalPeers, connect_offline, sender_id};
+    use iroh::endpoint::ConnectionError;
+    use std::time::Duration;
 
     fn init() -> (Endpoint, Arc<Runtime>) {
         let runtime = Arc::new(Runtime::new().unwrap());
@@ -217,4 +979,638 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_receiver_accepts_replacement_connection() -> Result<()> {
+        let config = NodeConfig {
+            auto_reconnect: true,
+            ..NodeConfig::offline(1)
+        };
+        let (mut receiver, mut sender, peer_id) = connect_offline(&config)?;
+
+        // Reset the connection while a receive is in flight
+        let pending = receiver.irecv(0)?;
+        receiver.runtime.block_on(async {
+            let connections = receiver.connections.lock().await;
+            connections[&peer_id]
+                .connection
+                .close(42u32.into(), b"reset");
+        });
+        let err = pending.wait().unwrap_err();
+        assert!(err.downcast_ref::<ConnectionReset>().is_some());
+
+        // Sender re-dials and the receiver accepts the replacement connection
+        let mut replaced = false;
+        for _ in 0..100 {
+            replaced = receiver.runtime.block_on(async {
+                let connections = receiver.connections.lock().await;
+                connections[&peer_id].connection.close_reason().is_none()
+            });
+            if replaced {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(replaced, "Sender did not reconnect");
+
+        // The sender may not have swapped in the new connection yet
+        let mut sent = false;
+        for _ in 0..100 {
+            sent = sender
+                .isend(b"after reset".to_vec(), 0, None)?
+                .wait()
+                .is_ok();
+            if sent {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(sent, "Sender did not send via the new connection");
+        assert_eq!(receiver.irecv(0)?.wait()?, b"after reset".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_rejects_duplicate_connection() -> Result<()> {
+        let config = NodeConfig::offline(1);
+        let (receiver, _sender, peer_id) = connect_offline(&config)?;
+
+        let connections = receiver.runtime.block_on(receiver.connections.lock());
+        assert!(ensure_replaceable(&connections, &peer_id, false).is_err());
+        assert!(ensure_replaceable(&connections, &peer_id, true).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_rejects_unknown_peer() -> Result<()> {
+        let config = NodeConfig {
+            allowed_peers: Some(HashSet::from([sender_id()])),
+            ..NodeConfig::offline(1)
+        };
+        let peers = LocalPeers::bind(&config, None)?;
+
+        // Connection is closed by the receiver without accepting any streams
+        let reason = peers
+            .runtime
+            .block_on(async { Ok::<_, Error>(peers.connect().await?.closed().await) })?;
+        match reason {
+            ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(close.error_code, REJECTED_CODE.into())
+            }
+            reason => panic!("Unexpected close reason: {}", reason),
+        }
+        assert!(!peers.receiver.is_ready());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_accepts_allowed_peer() -> Result<()> {
+        let config = NodeConfig {
+            allowed_peers: Some(HashSet::from([sender_id()])),
+            ..NodeConfig::offline(1)
+        };
+        let (mut receiver, mut sender, peer_id) = connect_offline(&config)?;
+        assert_eq!(receiver.peers(), vec![peer_id]);
+
+        sender.isend(b"allowed".to_vec(), 0, None)?.wait()?;
+        assert_eq!(receiver.irecv(0)?.wait()?, b"allowed".to_vec());
+
+        Ok(())
+    }
+
+    // Open a stream with the given preamble and wait for the receiver to close the connection
+    fn close_reason_for_preamble(
+        config: &NodeConfig,
+        preamble: Vec<u8>,
+    ) -> Result<ConnectionError> {
+        let peers = LocalPeers::bind(config, None)?;
+        peers.runtime.block_on(async {
+            let connection = peers.connect().await?;
+            let mut stream = connection.open_uni().await?;
+            stream.write_all(&preamble).await?;
+            Ok(connection.closed().await)
+        })
+    }
+
+    #[test]
+    fn test_receiver_rejects_invalid_cluster_token() -> Result<()> {
+        let config = NodeConfig {
+            cluster_token: Some(b"secret".to_vec()),
+            ..NodeConfig::offline(1)
+        };
+
+        // Missing proof and proof of a different token
+        for proof in [Vec::new(), vec![0; PROOF_LEN]] {
+            let preamble = Handshake::new(0, 1, proof)?.encode();
+            match close_reason_for_preamble(&config, preamble)? {
+                ConnectionError::ApplicationClosed(close) => {
+                    assert_eq!(close.error_code, UNAUTHORIZED_CODE.into())
+                }
+                reason => panic!("Unexpected close reason: {}", reason),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_accepts_valid_cluster_token() -> Result<()> {
+        let config = NodeConfig {
+            cluster_token: Some(b"secret".to_vec()),
+            ..NodeConfig::offline(2)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+
+        sender.isend(b"authorized".to_vec(), 1, None)?.wait()?;
+        assert_eq!(receiver.irecv(1)?.wait()?, b"authorized".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_rejects_incompatible_handshake() -> Result<()> {
+        let config = NodeConfig::offline(1);
+        let mut handshake = Handshake::new(0, 1, Vec::new())?;
+        handshake.version = PROTOCOL_VERSION + 1;
+        match close_reason_for_preamble(&config, handshake.encode())? {
+            ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(close.error_code, HANDSHAKE_CODE.into());
+                assert!(String::from_utf8_lossy(&close.reason).contains("Protocol version"));
+            }
+            reason => panic!("Unexpected close reason: {}", reason),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_adopts_sender_stream_count() -> Result<()> {
+        let recv_config = NodeConfig {
+            max_recv_streams: Some(4),
+            ..NodeConfig::offline(1)
+        };
+        let peers = LocalPeers::bind(&recv_config, None)?;
+        let mut sender = peers.sender(&NodeConfig::offline(3));
+        sender.connect_addr(peers.recv_addr, 3, &RetryPolicy::new(10))?;
+        let mut receiver = peers.receiver;
+
+        // Receiver accepts all three streams of the sender
+        sender.isend(b"last stream".to_vec(), 2, None)?.wait()?;
+        assert_eq!(receiver.irecv(2)?.wait()?, b"last stream".to_vec());
+        let connections = receiver.runtime.block_on(receiver.connections.lock());
+        let connection = connections.values().next().unwrap();
+        assert_eq!(
+            receiver
+                .runtime
+                .block_on(connection.recv_streams.lock())
+                .len(),
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_accepts_lazily_opened_tags() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Receive before the sender opened the stream of the tag
+        let pending = receiver.irecv(7)?;
+        sender.isend(b"tag 7".to_vec(), 7, None)?.wait()?;
+        sender.isend(b"tag 3".to_vec(), 3, None)?.wait()?;
+        assert_eq!(pending.wait()?, b"tag 7".to_vec());
+        assert_eq!(receiver.irecv(3)?.wait()?, b"tag 3".to_vec());
+
+        // Streams are reused once opened
+        sender.isend(b"tag 7 again".to_vec(), 7, None)?.wait()?;
+        assert_eq!(receiver.irecv(7)?.wait()?, b"tag 7 again".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_messages_of_a_tag_arrive_in_program_order() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Later messages have a shorter latency, but are still written after earlier ones
+        let num_messages = 10;
+        let recv_works = (0..num_messages)
+            .map(|_| receiver.irecv(0))
+            .collect::<Result<Vec<_>>>()?;
+        let send_works = (0..num_messages)
+            .map(|i| sender.isend(vec![i as u8], 0, Some((num_messages - i) * 10)))
+            .collect::<Result<Vec<_>>>()?;
+        for work in send_works {
+            work.wait()?;
+        }
+        for (i, work) in recv_works.into_iter().enumerate() {
+            assert_eq!(work.wait_with_seq()?, (vec![i as u8], i as u64));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_resets_stream_of_oversized_message() -> Result<()> {
+        let config = NodeConfig {
+            max_message_size: Some(16),
+            ..NodeConfig::offline(1)
+        };
+        let mut peers = LocalPeers::bind(&config, None)?;
+        let runtime = peers.runtime.clone();
+
+        // Sender that ignores the limit announced by the receiver
+        let (connection, mut stream) = runtime.block_on(async {
+            let (connection, mut stream, ack) = peers.handshake().await?;
+            assert_eq!(ack.max_message_size, Some(16));
+            // Length beyond 32 bits, which must not be truncated
+            stream
+                .write_all(&FrameHeader::new((1 << 32) + 1).encode())
+                .await?;
+            Ok::<_, Error>((connection, stream))
+        })?;
+        let err = peers.receiver.irecv(0)?.wait().unwrap_err();
+        let err = err.downcast_ref::<MessageTooLarge>().unwrap();
+        assert_eq!(err.size, (1 << 32) + 1);
+
+        // Only the stream was reset, the tag can be reopened on the same connection
+        let code = runtime.block_on(stream.stopped())?;
+        assert_eq!(code, Some(MESSAGE_TOO_LARGE_CODE.into()));
+        runtime.block_on(async {
+            let mut stream = connection.open_uni().await?;
+            stream.write_all(&TagHeader::new(0)?.encode()).await?;
+            let header = FrameHeader {
+                seq: 1,
+                ..FrameHeader::new(4)
+            };
+            stream.write_all(&header.encode()).await?;
+            stream.write_all(b"next").await?;
+            Ok::<_, Error>(())
+        })?;
+        assert_eq!(peers.receiver.irecv(0)?.wait()?, b"next".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_resets_stream_of_unskippable_message() -> Result<()> {
+        let mut peers = LocalPeers::bind(&NodeConfig::offline(1), None)?;
+        let runtime = peers.runtime.clone();
+
+        // Without a limit, the message fits but can't be skipped past its trailer
+        let (_connection, mut stream) = runtime.block_on(async {
+            let (connection, mut stream, _) = peers.handshake().await?;
+            let header = FrameHeader {
+                checksum: Checksum::Xxh3,
+                ..FrameHeader::new(u64::MAX)
+            };
+            stream.write_all(&header.encode()).await?;
+            Ok::<_, Error>((connection, stream))
+        })?;
+        let err = peers
+            .receiver
+            .irecv_into(0, vec![0; 4])?
+            .wait()
+            .unwrap_err();
+        let err = err.downcast_ref::<MessageTooLarge>().unwrap();
+        assert_eq!(err.size, u64::MAX);
+        let code = runtime.block_on(stream.stopped())?;
+        assert_eq!(code, Some(MESSAGE_TOO_LARGE_CODE.into()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_verifies_checksums() -> Result<()> {
+        let mut peers = LocalPeers::bind(&NodeConfig::offline(1), None)?;
+        let runtime = peers.runtime.clone();
+
+        // Sender that garbles the second message of the tag
+        let header = |seq| FrameHeader {
+            checksum: Checksum::Xxh3,
+            seq,
+            ..FrameHeader::new(4)
+        };
+        let _connection = runtime.block_on(async {
+            let (connection, mut stream, _) = peers.handshake().await?;
+            // The trailer of the garbled message is the one of the original
+            let frames = [(b"good", b"good"), (b"gxxd", b"good"), (b"next", b"next")];
+            for (seq, (msg, original)) in frames.into_iter().enumerate() {
+                stream.write_all(&header(seq as u64).encode()).await?;
+                stream.write_all(msg).await?;
+                stream.write_all(&Checksum::Xxh3.trailer(original)).await?;
+            }
+            Ok::<_, Error>(connection)
+        })?;
+        assert_eq!(peers.receiver.irecv(0)?.wait()?, b"good".to_vec());
+
+        // The garbled message fails with its tag and sequence number, the stream stays
+        // usable
+        let err = peers.receiver.irecv(0)?.wait().unwrap_err();
+        let err = err.downcast_ref::<ChecksumMismatch>().unwrap();
+        assert_eq!((err.tag, err.seq), (0, 1));
+        assert_eq!(peers.receiver.irecv(0)?.wait()?, b"next".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_checks_sequence_numbers() -> Result<()> {
+        let mut peers = LocalPeers::bind(&NodeConfig::offline(1), None)?;
+        let runtime = peers.runtime.clone();
+
+        // Sender that skips a message and then repeats one
+        let _connection = runtime.block_on(async {
+            let (connection, mut stream, _) = peers.handshake().await?;
+            for (seq, msg) in [(0, b"zero"), (2, b"two!"), (2, b"two!"), (3, b"tree")] {
+                let header = FrameHeader {
+                    seq,
+                    ..FrameHeader::new(4)
+                };
+                stream.write_all(&header.encode()).await?;
+                stream.write_all(msg).await?;
+            }
+            Ok::<_, Error>(connection)
+        })?;
+
+        // Messages come with their sequence number, gaps are tolerated
+        assert_eq!(
+            peers.receiver.irecv(0)?.wait_with_seq()?,
+            (b"zero".to_vec(), 0)
+        );
+        assert_eq!(
+            peers.receiver.irecv(0)?.wait_with_seq()?,
+            (b"two!".to_vec(), 2)
+        );
+
+        // Duplicates fail, without breaking the stream
+        let err = peers.receiver.irecv(0)?.wait().unwrap_err();
+        let err = err.downcast_ref::<DuplicateMessage>().unwrap();
+        assert_eq!((err.tag, err.seq, err.expected), (0, 2, 3));
+        assert_eq!(
+            peers.receiver.irecv(0)?.wait_with_seq()?,
+            (b"tree".to_vec(), 3)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receiver_checks_sequence_numbers_across_reconnects() -> Result<()> {
+        // Connect to the receiver and send the given frames of tag 0
+        fn send(peers: &LocalPeers, frames: &[(u64, &[u8; 4])]) -> Result<Connection> {
+            peers.runtime.block_on(async {
+                let (connection, mut stream, _) = peers.handshake().await?;
+                for &(seq, msg) in frames {
+                    let header = FrameHeader {
+                        seq,
+                        ..FrameHeader::new(4)
+                    };
+                    stream.write_all(&header.encode()).await?;
+                    stream.write_all(msg).await?;
+                }
+                Ok(connection)
+            })
+        }
+
+        let config = NodeConfig {
+            auto_reconnect: true,
+            ..NodeConfig::offline(1)
+        };
+        let mut peers = LocalPeers::bind(&config, None)?;
+
+        let _lost = send(&peers, &[(0, b"zero"), (1, b"one!")])?;
+        assert_eq!(peers.receiver.irecv(0)?.wait()?, b"zero".to_vec());
+        assert_eq!(peers.receiver.irecv(0)?.wait()?, b"one!".to_vec());
+
+        // The replacement connection resends a message that was already received
+        let _replacement = send(&peers, &[(1, b"one!"), (2, b"two!")])?;
+        let err = peers.receiver.irecv(0)?.wait().unwrap_err();
+        let err = err.downcast_ref::<DuplicateMessage>().unwrap();
+        assert_eq!((err.tag, err.seq, err.expected), (0, 1, 2));
+        assert_eq!(
+            peers.receiver.irecv(0)?.wait_with_seq()?,
+            (b"two!".to_vec(), 2)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksums_of_compressed_messages() -> Result<()> {
+        let config = NodeConfig {
+            compression: Compression {
+                codec: Codec::Lz4,
+                ..Compression::default()
+            },
+            checksum: Checksum::Crc32c,
+            ..NodeConfig::offline(1)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+        for msg in [vec![7; 65536], b"small".to_vec()] {
+            sender.isend(msg.clone(), 0, None)?.wait()?;
+            assert_eq!(receiver.irecv(0)?.wait()?, msg);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_messages() -> Result<()> {
+        let config = NodeConfig {
+            compression: Compression {
+                codec: Codec::Zstd,
+                ..Compression::default()
+            },
+            ..NodeConfig::offline(1)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+        assert_eq!(receiver.iprobe(0)?, None);
+        assert_eq!(receiver.iprobe(1)?, None);
+
+        // Probes report the uncompressed size without consuming the message
+        sender.isend(vec![7; 65536], 0, None)?.wait()?;
+        assert_eq!(receiver.probe(0)?, 65536);
+        assert_eq!(receiver.iprobe(0)?, Some(65536));
+        assert_eq!(receiver.irecv(0)?.wait_with_seq()?, (vec![7; 65536], 0));
+        assert_eq!(receiver.iprobe(0)?, None);
+
+        // Messages that earlier receives are waiting for are not reported
+        let pending = receiver.irecv(0)?;
+        let probe = std::thread::scope(|scope| {
+            let probe = scope.spawn(|| receiver.probe(0));
+            sender.isend(b"first", 0, None)?.wait()?;
+            sender.isend(b"second!", 0, None)?.wait()?;
+            probe.join().unwrap()
+        })?;
+        assert_eq!(probe, 7);
+        assert_eq!(pending.wait()?, b"first");
+        assert_eq!(receiver.iprobe(0)?, Some(7));
+        assert_eq!(receiver.irecv(0)?.wait()?, b"second!");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_any_tag() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Messages of any tag are received together with their tag, including tags that
+        // are opened while waiting
+        sender.isend(b"two", 2, None)?.wait()?;
+        assert_eq!(receiver.irecv_any()?.wait()?, (2, b"two".to_vec()));
+        let pending = receiver.irecv_any()?;
+        sender.isend(b"three", 3, None)?.wait()?;
+        assert_eq!(pending.wait_with_seq()?, ((3, b"three".to_vec()), 0));
+
+        // Probed messages are received normally
+        sender.isend(b"probed", 0, None)?.wait()?;
+        assert_eq!(receiver.probe(0)?, 6);
+        assert_eq!(receiver.irecv_any()?.wait()?, (0, b"probed".to_vec()));
+
+        // Tags that other receives are waiting for are left to them
+        let pending = receiver.irecv(0)?;
+        let pending_any = receiver.irecv_any()?;
+        sender.isend(b"zero", 0, None)?.wait()?;
+        assert_eq!(pending.wait()?, b"zero");
+        sender.isend(b"two again", 2, None)?.wait()?;
+        assert_eq!(pending_any.wait()?, (2, b"two again".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_any_tag_leaves_streams_to_probes() -> Result<()> {
+        let (mut receiver, mut sender, sender_id) = connect_offline(&NodeConfig::offline(1))?;
+        sender.isend(b"zero", 0, None)?.wait()?;
+        assert_eq!(receiver.irecv(0)?.wait()?, b"zero");
+
+        // Receives of any tag don't keep the streams locked while waiting for messages
+        let pending = receiver.irecv_any()?;
+        std::thread::sleep(Duration::from_millis(100));
+        let stream = receiver.runtime.block_on(async {
+            let connection = get_connection(&receiver.connections, sender_id).await?;
+            let stream = connection.recv_streams.lock().await[&0].clone();
+            Ok::<_, Error>(stream)
+        })?;
+        assert!(stream.try_lock().is_ok());
+        assert_eq!(receiver.iprobe(0)?, None);
+
+        // Probes polling the stream in between don't keep the message from the receive
+        let probe = std::thread::scope(|scope| {
+            let probe = scope.spawn(|| receiver.probe(0));
+            sender.isend(b"any", 0, None)?.wait()?;
+            probe.join().unwrap()
+        })?;
+        assert_eq!(probe, 3);
+        assert_eq!(pending.wait()?, (0, b"any".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_into_buffer() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Messages are received into (and returned with) the buffer of the caller
+        sender.isend(b"into", 0, None)?.wait()?;
+        let buffer = receiver.irecv_into(0, Box::<[u8]>::from([0; 4]))?.wait()?;
+        assert_eq!(&*buffer, b"into");
+
+        // Messages that don't match the size of the buffer are dropped, later messages of
+        // the tag are received normally
+        sender.isend(b"mismatch", 0, None)?.wait()?;
+        sender.isend(b"next", 0, None)?.wait()?;
+        let err = receiver.irecv_into(0, vec![0; 4])?.wait().unwrap_err();
+        let err = err.downcast_ref::<MessageSizeMismatch>().unwrap();
+        assert_eq!((err.size, err.buffer_size), (8, 4));
+        assert_eq!(
+            receiver.irecv_into(0, vec![0; 4])?.wait_with_seq()?,
+            (b"next".to_vec(), 2)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_messages() -> Result<()> {
+        let config = NodeConfig {
+            compression: Compression {
+                codec: Codec::Zstd,
+                tags: HashMap::from([(1, Codec::Lz4)]),
+                threshold: 1024,
+            },
+            ..NodeConfig::offline(2)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+
+        // Messages are decompressed transparently, whatever their codec or size
+        let msg: Vec<u8> = (0..65536).map(|i| (i % 13) as u8).collect();
+        for tag in [0, 1] {
+            sender.isend(msg.clone(), tag, None)?.wait()?;
+            assert_eq!(receiver.irecv(tag)?.wait()?, msg);
+            sender.isend(b"small", tag, None)?.wait()?;
+            assert_eq!(receiver.irecv(tag)?.wait()?, b"small");
+        }
+
+        // Receive buffers match the uncompressed size of messages
+        sender.isend(msg.clone(), 0, None)?.wait()?;
+        let buffer = receiver.irecv_into(0, vec![0; msg.len()])?.wait()?;
+        assert_eq!(buffer, msg);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_downcast_tensors() -> Result<()> {
+        let config = NodeConfig {
+            compression: Compression {
+                codec: Codec::Zstd,
+                ..Compression::default()
+            },
+            tensor_precision: TensorPrecision {
+                precision: Precision::BF16,
+                tags: HashMap::from([(1, Precision::FP8)]),
+            },
+            ..NodeConfig::offline(2)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+
+        // Float32 tensors are restored to float32, whatever their precision and codec
+        let data = (0..4096).flat_map(|i| (i as f32).to_ne_bytes()).collect();
+        let tensor = Tensor::new(DType::F32, vec![64, 64], data)?;
+        for tag in [0, 1] {
+            sender.isend_tensor(&tensor, tag, None)?.wait()?;
+            let recv = Tensor::decode(receiver.irecv(tag)?.wait()?)?;
+            assert_eq!((recv.dtype, &recv.shape), (DType::F32, &tensor.shape));
+        }
+
+        // Other tensors are sent as is
+        let tensor = Tensor::new(DType::I32, vec![4], vec![7; 16])?;
+        sender.isend_tensor(&tensor, 0, None)?.wait()?;
+        assert_eq!(Tensor::decode(receiver.irecv(0)?.wait()?)?, tensor);
+
+        // Probes report, and receive buffers match, the restored size of tensors
+        let tensor = Tensor::new(DType::F32, vec![4], vec![0; 16])?;
+        let size = tensor.encode()?.len();
+        sender.isend_tensor(&tensor, 0, None)?.wait()?;
+        assert_eq!(receiver.probe(0)?, size);
+        let buffer = receiver.irecv_into(0, vec![0; size])?.wait()?;
+        assert_eq!(
+            Tensor::decode(buffer)?,
+            tensor.clone().into_contiguous(ByteOrder::Little)
+        );
+        sender.isend_tensor(&tensor, 0, None)?.wait()?;
+        let err = receiver
+            .irecv_into(0, vec![0; size - 8])?
+            .wait()
+            .unwrap_err();
+        assert!(err.downcast_ref::<MessageSizeMismatch>().is_some());
+        sender.isend(b"next", 0, None)?.wait()?;
+        assert_eq!(receiver.irecv(0)?.wait()?, b"next");
+
+        Ok(())
+    }
 }
@@ -0,0 +1,127 @@
+use anyhow::{Result, ensure};
+use rand::Rng;
+use std::time::Duration;
+
+/// Policy for retrying failed connection attempts with exponential backoff
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of connection attempts
+    pub max_retries: usize,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Factor by which the delay grows after every failed attempt
+    pub backoff: f64,
+    /// Fraction by which each delay is randomly shortened or lengthened (0 disables jitter)
+    pub jitter: f64,
+    /// Upper bound for the delay between two attempts
+    pub max_delay: Duration,
+    /// Optional upper bound for the total time spent connecting
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 10,
+            initial_delay: Duration::from_millis(100),
+            backoff: 2.0,
+            jitter: 0.1,
+            max_delay: Duration::from_secs(10),
+            deadline: None,
+        }
+    }
+}
+
+impl From<usize> for RetryPolicy {
+    fn from(max_retries: usize) -> Self {
+        Self::new(max_retries)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.max_retries > 0, "Retry policy needs at least one try");
+        ensure!(
+            self.backoff >= 1.0,
+            "Retry backoff must be at least 1.0, got {}",
+            self.backoff
+        );
+        ensure!(
+            (0.0..=1.0).contains(&self.jitter),
+            "Retry jitter must be between 0.0 and 1.0, got {}",
+            self.jitter
+        );
+        Ok(())
+    }
+
+    /// Delay before the next attempt after `attempt` failed attempts (starting at 1)
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.backoff.powi(exponent);
+        let delay = if self.jitter > 0.0 {
+            delay * rand::thread_rng().gen_range(1.0 - self.jitter..=1.0 + self.jitter)
+        } else {
+            delay
+        };
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RetryPolicy::default().validate().is_ok());
+        assert!(RetryPolicy::new(0).validate().is_err());
+        assert!(
+            RetryPolicy {
+                backoff: 0.5,
+                ..RetryPolicy::default()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            RetryPolicy {
+                jitter: 1.5,
+                ..RetryPolicy::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -1,26 +1,308 @@
-use anyhow::{Error, Result, ensure};
+use anyhow::{Error, Result, anyhow, bail, ensure};
 use iroh::{
     Endpoint, NodeAddr, NodeId,
-    endpoint::{Connection, ConnectionError, SendStream},
+    endpoint::{Connection, ConnectionError, SendStream, WriteError},
 };
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OnceCell};
 
-use crate::work::SendWork;
+use crate::auth::cluster_proof;
+use crate::error::{
+    ConnectionRejected, MessageTooLarge, StreamStopped, map_connection_rejected,
+    map_connection_reset,
+};
+use crate::frame::{Checksum, Codec, Compression, FrameHeader};
+use crate::handshake::{Handshake, MAX_STREAMS, TagHeader};
+use crate::node::{NodeConfig, parse_peer_addr};
+use crate::precision::{Precision, TensorPrecision};
+use crate::queue::TurnQueue;
+use crate::retry::RetryPolicy;
+use crate::tensor::{Tensor, encode_tensors};
+use crate::work::{ConnectWork, SendWork};
 
 const ALPN: &[u8] = b"prime-iroh";
 
+/// Message that can be sent without copying it, e.g. `Vec<u8>`, `Arc<[u8]>` or a static
+/// byte slice. It is held until the send completes
+pub trait Message: AsRef<[u8]> + Send + Sync + 'static {}
+
+impl<T: AsRef<[u8]> + Send + Sync + 'static> Message for T {}
+
+// Stream of a tag, opened by the first send of the tag while later sends wait for it
+type StreamCell = Arc<OnceCell<Arc<Mutex<SendStream>>>>;
+
+type SendStreams = Arc<Mutex<HashMap<usize, StreamCell>>>;
+
 pub struct MultiStreamConnection {
     connection: Connection,
-    send_streams: Vec<Arc<Mutex<SendStream>>>,
+    send_streams: SendStreams,
+    // Maximum size of messages the peer accepts, if limited
+    max_message_size: Option<usize>,
 }
 
 impl MultiStreamConnection {
-    pub fn new(connection: Connection, send_streams: Vec<Arc<Mutex<SendStream>>>) -> Self {
+    /// Connection whose tags start out with the given streams (the stream of tag `i` at
+    /// index `i`), all other tags are opened on first use
+    pub fn new(
+        connection: Connection,
+        send_streams: Vec<Arc<Mutex<SendStream>>>,
+        max_message_size: Option<usize>,
+    ) -> Self {
         Self {
             connection,
-            send_streams,
+            send_streams: Arc::new(Mutex::new(
+                send_streams
+                    .into_iter()
+                    .map(|stream| Arc::new(OnceCell::from(stream)))
+                    .enumerate()
+                    .collect(),
+            )),
+            max_message_size,
+        }
+    }
+}
+
+type Connections = Arc<Mutex<HashMap<NodeId, MultiStreamConnection>>>;
+
+// Forget the stream of a tag that the receiver reset, unless it was already replaced
+async fn forget_stream(send_streams: &SendStreams, tag: usize, stream: &Arc<Mutex<SendStream>>) {
+    let mut send_streams = send_streams.lock().await;
+    if send_streams
+        .get(&tag)
+        .and_then(|cell| cell.get())
+        .is_some_and(|current| Arc::ptr_eq(current, stream))
+    {
+        send_streams.remove(&tag);
+    }
+}
+
+// Write a message prefixed with its frame header
+async fn write_message(
+    stream: &mut SendStream,
+    header: &FrameHeader,
+    msg: &[u8],
+    trailer: &[u8],
+) -> Result<(), WriteError> {
+    // Write the header of the message
+    stream.write_all(&header.encode()).await?;
+
+    // Write the message, followed by its checksum (if any)
+    stream.write_all(msg).await?;
+    stream.write_all(trailer).await
+}
+
+// Get the stream of a tag, opening it and announcing its tag to the receiver on first use
+async fn get_or_open_stream(
+    connection: &Connection,
+    send_streams: &SendStreams,
+    tag: usize,
+) -> Result<Arc<Mutex<SendStream>>> {
+    let tag_header = TagHeader::new(tag)?.encode();
+
+    // Opening a stream waits for the peer, so the streams are only locked to get the cell of
+    // the tag, and sends of other tags go ahead meanwhile
+    let cell = {
+        let mut send_streams = send_streams.lock().await;
+        match send_streams.get(&tag) {
+            Some(cell) => cell.clone(),
+            None => {
+                // Beyond its limit, the peer would never grant the stream
+                ensure!(
+                    send_streams.len() < MAX_STREAMS,
+                    "Cannot open a stream for tag {}, peers accept at most {} streams (tags) per connection",
+                    tag,
+                    MAX_STREAMS
+                );
+                send_streams.entry(tag).or_default().clone()
+            }
+        }
+    };
+    let stream = cell
+        .get_or_try_init(|| async {
+            let mut stream = connection.open_uni().await?;
+            stream.write_all(&tag_header).await?;
+            log::debug!("Opened stream for tag {}", tag);
+            Ok::<_, Error>(Arc::new(Mutex::new(stream)))
+        })
+        .await?;
+    Ok(stream.clone())
+}
+
+// Single attempt to connect to a peer and open all streams
+async fn open_connection(
+    endpoint: &Endpoint,
+    peer_addr: NodeAddr,
+    num_streams: usize,
+    cluster_token: Option<&[u8]>,
+) -> Result<MultiStreamConnection> {
+    // Prove knowledge of the cluster token, if any (an empty proof otherwise)
+    let peer_id = peer_addr.node_id;
+    let proof = cluster_token
+        .map(|token| cluster_proof(token, &endpoint.node_id(), &peer_id))
+        .unwrap_or_default();
+
+    // Try to establish connection
+    let connection = endpoint.connect(peer_addr, ALPN).await?;
+
+    // Establish streams by sending a handshake on each of them
+    let mut send_streams = Vec::with_capacity(num_streams);
+    for stream_index in 0..num_streams {
+        let mut send_stream = connection.open_uni().await?;
+        Handshake::new(stream_index, num_streams, proof.clone())?
+            .write(&mut send_stream)
+            .await?;
+        send_streams.push(Arc::new(Mutex::new(send_stream)));
+    }
+
+    // Wait for the receiver to acknowledge the handshake (or to reject the connection)
+    let ack = async {
+        let mut ack_stream = connection.accept_uni().await?;
+        Handshake::read(&mut ack_stream).await
+    }
+    .await;
+    let ack = ack.map_err(|e| map_connection_rejected(&connection, peer_id, e))?;
+
+    // Limits beyond the address space are no limits
+    let max_message_size = ack
+        .max_message_size
+        .and_then(|size| usize::try_from(size).ok());
+    Ok(MultiStreamConnection::new(
+        connection,
+        send_streams,
+        max_message_size,
+    ))
+}
+
+// Connection loop, retrying failed attempts according to the retry policy
+async fn connect_with_retries(
+    endpoint: &Endpoint,
+    peer_addr: NodeAddr,
+    num_streams: usize,
+    retry_policy: &RetryPolicy,
+    cluster_token: Option<&[u8]>,
+) -> Result<MultiStreamConnection> {
+    let start = Instant::now();
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        let attempt = open_connection(endpoint, peer_addr.clone(), num_streams, cluster_token);
+
+        // Bound the attempt by the time left until the deadline
+        let result = match retry_policy.deadline {
+            Some(deadline) => {
+                tokio::time::timeout(deadline.saturating_sub(start.elapsed()), attempt)
+                    .await
+                    .map_err(|_| anyhow!("Connection attempt timed out"))
+                    .and_then(|result| result)
+            }
+            None => attempt.await,
+        };
+
+        let e = match result {
+            Ok(connection) => return Ok(connection),
+            // Retrying a connection that the peer refused does not help
+            Err(e) if e.is::<ConnectionRejected>() => return Err(e),
+            Err(e) => e,
+        };
+        let retries_left = retry_policy.max_retries - tries;
+        if retries_left == 0 {
+            return Err(e);
+        }
+
+        // Give up early if the next attempt would start after the deadline
+        let delay = retry_policy.delay(tries);
+        if let Some(deadline) = retry_policy.deadline
+            && start.elapsed() + delay >= deadline
+        {
+            return Err(e.context(format!(
+                "Connection deadline of {:?} exceeded after {} tries",
+                deadline, tries
+            )));
+        }
+
+        if let Some(_connection_error) = e.downcast_ref::<ConnectionError>() {
+            // Connection fails if the discovery succeeds but the connnection fails (node is still booting up)
+            log::warn!(
+                "Connection failed after {} tries (left: {}). Retrying in {:?}...",
+                tries,
+                retries_left,
+                delay
+            );
+        } else {
+            // This is likely a discovery error which happens when the node address is not yet available
+            log::warn!(
+                "Unexpected error during connection after {} tries (left: {}). It's likely that address information via discovery is not yet available. Retrying in {:?}...",
+                tries,
+                retries_left,
+                delay
+            );
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Re-dial a peer whenever its connection is lost, until the sender is closed
+async fn reconnect_on_close(
+    endpoint: Endpoint,
+    connections: Connections,
+    peer_addr: NodeAddr,
+    num_streams: usize,
+    retry_policy: RetryPolicy,
+    cluster_token: Option<Arc<[u8]>>,
+    mut connection: Connection,
+) {
+    let peer_id = peer_addr.node_id;
+    loop {
+        // Closing the sender or the peer closing its endpoint is not a reset
+        match connection.closed().await {
+            ConnectionError::LocallyClosed => return,
+            ConnectionError::ApplicationClosed(close) if close.error_code == 0u32.into() => return,
+            reason => log::warn!(
+                "Connection {}->{} reset ({}), reconnecting",
+                endpoint.node_id().fmt_short(),
+                peer_id.fmt_short(),
+                reason
+            ),
+        }
+
+        // Re-establish the connection and all streams
+        let result = connect_with_retries(
+            &endpoint,
+            peer_addr.clone(),
+            num_streams,
+            &retry_policy,
+            cluster_token.as_deref(),
+        )
+        .await;
+        let mut connections = connections.lock().await;
+        match result {
+            Ok(new_connection) => {
+                if !connections.contains_key(&peer_id) {
+                    // Sender was closed in the meantime
+                    new_connection.connection.close(0u32.into(), b"close");
+                    return;
+                }
+                log::info!(
+                    "Reconnected {}->{}",
+                    endpoint.node_id().fmt_short(),
+                    peer_id.fmt_short()
+                );
+                connection = new_connection.connection.clone();
+                connections.insert(peer_id, new_connection);
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to reconnect {}->{}: {}",
+                    endpoint.node_id().fmt_short(),
+                    peer_id.fmt_short(),
+                    e
+                );
+                connections.remove(&peer_id);
+                return;
+            }
         }
     }
 }
@@ -28,139 +310,360 @@ impl MultiStreamConnection {
 pub struct Sender {
     runtime: Arc<Runtime>,
     endpoint: Endpoint,
-    connection: Option<MultiStreamConnection>,
+    connections: Connections,
+    auto_reconnect: bool,
+    cluster_token: Option<Arc<[u8]>>,
+    compression: Compression,
+    tensor_precision: TensorPrecision,
+    checksum: Checksum,
+    // Sequence number of the next message to each peer and tag, kept across reconnects
+    send_seqs: HashMap<(NodeId, usize), u64>,
+    send_turns: TurnQueue<(NodeId, usize)>,
 }
 
 impl Sender {
     pub fn new(runtime: Arc<Runtime>, endpoint: Endpoint) -> Self {
+        Self::with_config(runtime, endpoint, &NodeConfig::default())
+    }
+
+    pub fn with_config(runtime: Arc<Runtime>, endpoint: Endpoint, config: &NodeConfig) -> Self {
         log::info!("Creating sender (ID={})", endpoint.node_id().fmt_short());
         Self {
             runtime,
             endpoint,
-            connection: None,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            auto_reconnect: config.auto_reconnect,
+            cluster_token: config.cluster_token.as_deref().map(Arc::from),
+            compression: config.compression.clone(),
+            tensor_precision: config.tensor_precision.clone(),
+            checksum: config.checksum,
+            send_seqs: HashMap::new(),
+            send_turns: TurnQueue::default(),
         }
     }
 
     pub fn is_ready(&self) -> bool {
-        self.connection.is_some()
+        self.runtime.block_on(async {
+            let connections = self.connections.lock().await;
+            !connections.is_empty()
+        })
+    }
+
+    pub fn peers(&self) -> Vec<NodeId> {
+        self.runtime.block_on(async {
+            let connections = self.connections.lock().await;
+            connections.keys().copied().collect()
+        })
     }
 
     pub fn connect(
         &mut self,
         peer_id_str: String,
         num_streams: usize,
-        num_retries: usize,
+        retry_policy: &RetryPolicy,
     ) -> Result<()> {
-        // Ensure we don't already have a connection
-        ensure!(self.connection.is_none(), "Already have a connection");
+        self.iconnect(peer_id_str, num_streams, retry_policy)?
+            .wait()
+    }
 
-        // Get the peer address from the node id
-        let peer_addr = self.get_node_addr(peer_id_str.clone())?;
+    pub fn connect_addr(
+        &mut self,
+        peer_addr: NodeAddr,
+        num_streams: usize,
+        retry_policy: &RetryPolicy,
+    ) -> Result<()> {
+        self.iconnect_addr(peer_addr, num_streams, retry_policy)?
+            .wait()
+    }
+
+    pub fn iconnect(
+        &mut self,
+        peer_id_str: String,
+        num_streams: usize,
+        retry_policy: &RetryPolicy,
+    ) -> Result<ConnectWork> {
+        // Get the peer address from the node id or ticket
+        let peer_addr = parse_peer_addr(&peer_id_str)?;
+        self.iconnect_addr(peer_addr, num_streams, retry_policy)
+    }
+
+    pub fn iconnect_addr(
+        &mut self,
+        peer_addr: NodeAddr,
+        num_streams: usize,
+        retry_policy: &RetryPolicy,
+    ) -> Result<ConnectWork> {
+        retry_policy.validate()?;
+        ensure!(num_streams > 0, "Need at least one stream");
+        ensure!(
+            num_streams <= MAX_STREAMS,
+            "Need at most {} streams, got {}",
+            MAX_STREAMS,
+            num_streams
+        );
+
+        // Ensure we don't already have a connection to this peer
+        let peer_id = peer_addr.node_id;
+        ensure!(
+            !self.peers().contains(&peer_id),
+            "Already have a connection to peer {}",
+            peer_id.fmt_short()
+        );
 
         log::info!(
             "Connecting {}->{}",
             self.endpoint.node_id().fmt_short(),
-            peer_addr.node_id.fmt_short()
+            peer_id.fmt_short()
         );
 
-        // Connection loop
-        let mut retries_left = num_retries;
-        while retries_left > 0 {
-            match self.runtime.block_on(async {
-                // Try to establish connection
-                let connection = self.endpoint.connect(peer_addr.clone(), ALPN).await?;
-
-                // Establish streams by sending dummy payload
-                let mut send_streams = Vec::with_capacity(num_streams);
-                for _ in 0..num_streams {
-                    let send_stream = Arc::new(Mutex::new(connection.open_uni().await?));
-                    send_stream
-                        .lock()
-                        .await
-                        .write_all(&(0u32.to_le_bytes()))
-                        .await?;
-                    send_streams.push(send_stream);
-                }
-
-                Ok::<MultiStreamConnection, Error>(MultiStreamConnection::new(
-                    connection,
-                    send_streams,
-                ))
-            }) {
-                Ok(connection) => {
-                    log::info!(
-                        "Connected {}->{}",
-                        self.endpoint.node_id().fmt_short(),
-                        connection.connection.remote_node_id()?.fmt_short()
-                    );
-                    self.connection = Some(connection);
-                    return Ok(());
-                }
-                Err(e) => {
-                    retries_left -= 1;
-                    if let Some(_connection_error) = e.downcast_ref::<ConnectionError>() {
-                        // Connection fails if the discovery succeeds but the connnection fails (node is still booting up)
-                        let msg = format!(
-                            "Connection failed after {} tries (left: {})",
-                            num_retries - retries_left,
-                            retries_left,
-                        );
-                        log::warn!("{}", msg);
-                    } else {
-                        // This is likely a discovery error which happens when the node address is not yet available
-                        // TODO(Mika): Handle this more elegantly
-                        let msg = format!(
-                            "Unexpected error during connection after {} tries (left: {}). It's likely that address information via discovery is not yet available. Sleeping for 30s before retrying...",
-                            num_retries - retries_left,
-                            retries_left,
-                        );
-                        log::warn!("{}", msg);
-                        std::thread::sleep(std::time::Duration::from_secs(30));
-                    }
-
-                    if retries_left == 0 {
-                        return Err(e);
-                    }
-                }
+        let endpoint = self.endpoint.clone();
+        let connections = self.connections.clone();
+        let retry_policy = retry_policy.clone();
+        let auto_reconnect = self.auto_reconnect;
+        let cluster_token = self.cluster_token.clone();
+        let handle = self.runtime.spawn(async move {
+            let connection = connect_with_retries(
+                &endpoint,
+                peer_addr.clone(),
+                num_streams,
+                &retry_policy,
+                cluster_token.as_deref(),
+            )
+            .await?;
+
+            // Store the connection, unless a concurrent connect to the same peer won
+            let mut connections_guard = connections.lock().await;
+            if connections_guard.contains_key(&peer_id) {
+                connection.connection.close(0u32.into(), b"duplicate");
+                bail!("Already have a connection to peer {}", peer_id.fmt_short());
             }
-        }
-        unreachable!()
+            log::info!(
+                "Connected {}->{}",
+                endpoint.node_id().fmt_short(),
+                peer_id.fmt_short()
+            );
+            if auto_reconnect {
+                tokio::spawn(reconnect_on_close(
+                    endpoint.clone(),
+                    connections.clone(),
+                    peer_addr,
+                    num_streams,
+                    retry_policy,
+                    cluster_token,
+                    connection.connection.clone(),
+                ));
+            }
+            connections_guard.insert(peer_id, connection);
+            Ok(())
+        });
+        Ok(ConnectWork::new(self.runtime.clone(), handle))
     }
 
-    pub fn isend(&mut self, msg: Vec<u8>, tag: usize, latency: Option<usize>) -> Result<SendWork> {
-        // Ensure we have a connection
+    fn single_peer(&self) -> Result<NodeId> {
+        // Ensure we have exactly one connection
         ensure!(self.is_ready(), "Sender is not ready");
-        log::debug!("Sending {} bytes via stream {}", msg.len(), tag);
+        let peers = self.peers();
+        ensure!(
+            peers.len() == 1,
+            "Sender has {} connections, specify a peer to send to",
+            peers.len()
+        );
+        Ok(peers[0])
+    }
+
+    pub fn isend(
+        &mut self,
+        msg: impl Message,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.isend_to(self.single_peer()?, msg, tag, latency)
+    }
+
+    pub fn isend_to(
+        &mut self,
+        peer_id: NodeId,
+        msg: impl Message,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.isend_with(peer_id, msg, Precision::Full, tag, latency)
+    }
+
+    // Send a message, which is a downcast tensor unless the precision is full
+    fn isend_with(
+        &mut self,
+        peer_id: NodeId,
+        msg: impl Message,
+        precision: Precision,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        log::debug!(
+            "Sending {} bytes to {} via stream {}",
+            msg.as_ref().len(),
+            peer_id.fmt_short(),
+            tag
+        );
 
         // Get the sender connection
-        let connection = self.connection.as_ref().unwrap();
+        let (connection, send_streams, max_message_size) = self.runtime.block_on(async {
+            let connections = self.connections.lock().await;
+            let connection = connections
+                .get(&peer_id)
+                .ok_or_else(|| anyhow!("No connection to peer {}", peer_id.fmt_short()))?;
+            Ok::<_, Error>((
+                connection.connection.clone(),
+                connection.send_streams.clone(),
+                connection.max_message_size,
+            ))
+        })?;
+
+        // Downcast tensors announce their size once restored, so that the receiver knows it
+        // before reading them
+        let len = msg.as_ref().len();
+        let size =
+            u64::try_from(len).map_err(|_| anyhow!("Message of {} bytes cannot be framed", len))?;
+        let restored_size = match precision {
+            Precision::Full => size,
+            _ => u64::try_from(precision.restored_size(msg.as_ref())?)?,
+        };
+
+        // Don't send messages that the receiver would reject anyway
+        if let Some(max_size) = max_message_size
+            && size.max(restored_size) > max_size as u64
+        {
+            return Err(MessageTooLarge {
+                peer_id,
+                tag,
+                size: size.max(restored_size),
+                max_size,
+            }
+            .into());
+        }
 
-        // Get the stream
-        ensure!(tag < connection.send_streams.len(), "Invalid tag");
-        let stream = connection.send_streams[tag].clone();
+        // Messages of the same peer and tag are numbered and written in the order they
+        // were issued
+        let next_seq = self.send_seqs.entry((peer_id, tag)).or_default();
+        let seq = *next_seq;
+        *next_seq += 1;
+        let mut turn = self.send_turns.push((peer_id, tag));
+        let codec = self.compression.codec(tag, len);
+        let checksum = self.checksum;
 
         let handle = self.runtime.spawn(async move {
             if let Some(latency) = latency {
                 tokio::time::sleep(tokio::time::Duration::from_millis(latency as u64)).await;
             }
-            // Lock the stream
-            let mut stream = stream.lock().await;
 
-            // Write the size of the message
-            let size = msg.len() as u32;
-            stream.write_all(&size.to_le_bytes()).await?;
-
-            // Write the message
-            stream.write_all(&msg).await?;
+            // Compress the message (unless compression does not make it smaller) and
+            // checksum it off the runtime threads, before waiting for the turn
+            let msg = Arc::new(msg);
+            let (compressed, trailer) = match (codec, checksum) {
+                (Codec::None, Checksum::None) => (None, Vec::new()),
+                _ => {
+                    let raw = msg.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let raw = (*raw).as_ref();
+                        let compressed = codec
+                            .compress(raw)?
+                            .filter(|compressed| compressed.len() < len);
+                        let trailer = checksum.trailer(compressed.as_deref().unwrap_or(raw));
+                        Ok::<_, Error>((compressed, trailer))
+                    })
+                    .await??
+                }
+            };
+            let header = match &compressed {
+                Some(compressed) => FrameHeader {
+                    size: compressed.len() as u64,
+                    codec,
+                    precision,
+                    checksum,
+                    raw_size: size,
+                    restored_size,
+                    seq,
+                },
+                None => FrameHeader {
+                    precision,
+                    checksum,
+                    restored_size,
+                    seq,
+                    ..FrameHeader::new(size)
+                },
+            };
+            let body = compressed.as_deref().unwrap_or((*msg).as_ref());
+
+            turn.wait().await;
+            let result = async {
+                // Get and lock the stream, which is opened on first use of the tag
+                let stream = get_or_open_stream(&connection, &send_streams, tag).await?;
+                let result =
+                    write_message(&mut *stream.lock().await, &header, body, &trailer).await;
+
+                // The next message of the tag is sent on a new stream
+                if let Err(WriteError::Stopped(code)) = result {
+                    forget_stream(&send_streams, tag, &stream).await;
+                    return Err(StreamStopped { peer_id, tag, code }.into());
+                }
+                result?;
 
-            Ok(())
+                Ok(())
+            }
+            .await;
+            result.map_err(|e| map_connection_reset(&connection, peer_id, e))
         });
         Ok(SendWork {
             runtime: self.runtime.clone(),
-            handle: handle,
+            handle,
         })
     }
 
+    /// Send a tensor together with its dtype, shape and strides, downcasting float32
+    /// tensors to the precision of the tag
+    pub fn isend_tensor(
+        &mut self,
+        tensor: &Tensor,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.isend_tensor_to(self.single_peer()?, tensor, tag, latency)
+    }
+
+    pub fn isend_tensor_to(
+        &mut self,
+        peer_id: NodeId,
+        tensor: &Tensor,
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        let precision = self.tensor_precision.precision(tag);
+        match precision.downcast(tensor)? {
+            Some(msg) => self.isend_with(peer_id, msg, precision, tag, latency),
+            None => self.isend_to(peer_id, tensor.encode()?, tag, latency),
+        }
+    }
+
+    /// Send named tensors as a single message in the safetensors layout
+    pub fn isend_tensors(
+        &mut self,
+        tensors: &[Tensor],
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.isend(encode_tensors(tensors)?, tag, latency)
+    }
+
+    pub fn isend_tensors_to(
+        &mut self,
+        peer_id: NodeId,
+        tensors: &[Tensor],
+        tag: usize,
+        latency: Option<usize>,
+    ) -> Result<SendWork> {
+        self.isend_to(peer_id, encode_tensors(tensors)?, tag, latency)
+    }
+
     pub fn close(&mut self) -> Result<()> {
         if !self.is_ready() {
             log::warn!("Sender connection does not exist, skipping close");
@@ -171,10 +674,11 @@ impl Sender {
             self.endpoint.node_id().fmt_short()
         );
         match self.runtime.block_on(async {
-            let mut connection = self.connection.take();
-            if let Some(connection) = connection.as_mut() {
+            let mut connections = self.connections.lock().await;
+            for (_, connection) in connections.drain() {
                 // First flush all streams
-                for stream in connection.send_streams.iter() {
+                for cell in connection.send_streams.lock().await.values() {
+                    let Some(stream) = cell.get() else { continue };
                     let mut stream = stream.lock().await;
                     stream.finish()?; // Make sure all data is sent
                     stream.stopped().await?;
@@ -198,17 +702,14 @@ impl Sender {
             }
         }
     }
-
-    fn get_node_addr(&self, node_id_str: String) -> Result<NodeAddr> {
-        let bytes = hex::decode(node_id_str)?;
-        let node_id = NodeId::from_bytes(bytes.as_slice().try_into()?)?;
-        Ok(NodeAddr::new(node_id))
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{LocalPeers, connect_offline};
+    use iroh::SecretKey;
+    use std::time::Duration;
 
     fn init() -> (Endpoint, Arc<Runtime>) {
         let runtime = Arc::new(Runtime::new().unwrap());
@@ -238,6 +739,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sender_connect_deadline() -> Result<()> {
+        let (endpoint, runtime) = init();
+        let mut sender = Sender::new(runtime, endpoint);
+
+        // Nobody is listening at this address, so every attempt fails
+        let peer_id = SecretKey::generate(rand::thread_rng()).public();
+        let peer_addr = NodeAddr::new(peer_id).with_direct_addresses(["127.0.0.1:9".parse()?]);
+        let retry_policy = RetryPolicy {
+            max_retries: 100,
+            deadline: Some(Duration::from_millis(500)),
+            ..RetryPolicy::default()
+        };
+
+        let start = Instant::now();
+        let res = sender.connect_addr(peer_addr, 1, &retry_policy);
+        assert!(res.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!sender.is_ready());
+
+        Ok(())
+    }
+
     #[test]
     fn test_sender_ok_on_close() -> Result<()> {
         let (endpoint, runtime) = init();
@@ -248,4 +772,80 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_sender_connect_fails_on_too_many_streams() -> Result<()> {
+        let peers = LocalPeers::bind(&NodeConfig::offline(1), None)?;
+        let mut sender = peers.sender(&NodeConfig::offline(2));
+
+        // Rejected connections are not retried
+        let err = sender
+            .connect_addr(peers.recv_addr.clone(), 2, &RetryPolicy::new(10))
+            .unwrap_err();
+        assert!(err.is::<ConnectionRejected>());
+        assert!(err.to_string().contains("receiver accepts at most 1"));
+        assert!(!sender.is_ready());
+        assert!(!peers.receiver.is_ready());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sender_rejects_message_above_receiver_limit() -> Result<()> {
+        let config = NodeConfig {
+            max_message_size: Some(16),
+            ..NodeConfig::offline(1)
+        };
+        let (mut receiver, mut sender, _) = connect_offline(&config)?;
+
+        match sender.isend(vec![0; 17], 0, None) {
+            Err(e) => assert!(e.is::<MessageTooLarge>()),
+            Ok(_) => panic!("Oversized message was sent"),
+        }
+        sender.isend(vec![1; 16], 0, None)?.wait()?;
+        assert_eq!(receiver.irecv(0)?.wait()?, vec![1; 16]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_shared_and_static_messages() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Messages are sent from memory they share with the caller
+        let shared: Arc<[u8]> = Arc::from(vec![7; 1024]);
+        sender.isend(shared.clone(), 0, None)?.wait()?;
+        sender.isend(b"static", 0, None)?.wait()?;
+        assert_eq!(receiver.irecv(0)?.wait()?, shared.to_vec());
+        assert_eq!(receiver.irecv(0)?.wait()?, b"static".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sender_fails_beyond_stream_limit() -> Result<()> {
+        let (mut receiver, mut sender, _) = connect_offline(&NodeConfig::offline(1))?;
+
+        // Tag 0 uses the stream of the handshake, the others are opened concurrently
+        let works = (1..MAX_STREAMS)
+            .map(|tag| sender.isend(vec![1], tag, None))
+            .collect::<Result<Vec<_>>>()?;
+        for work in works {
+            work.wait()?;
+        }
+
+        // The peer would never grant another stream, so the send fails instead of hanging
+        let err = sender
+            .isend(vec![2], MAX_STREAMS, None)?
+            .wait()
+            .unwrap_err();
+        assert!(err.to_string().contains("at most 1024 streams"));
+
+        // Tags that have a stream are unaffected
+        sender.isend(vec![3], MAX_STREAMS - 1, None)?.wait()?;
+        assert_eq!(receiver.irecv(MAX_STREAMS - 1)?.wait()?, vec![1]);
+        assert_eq!(receiver.irecv(MAX_STREAMS - 1)?.wait()?, vec![3]);
+
+        Ok(())
+    }
 }
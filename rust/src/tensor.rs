@@ -0,0 +1,598 @@
+use anyhow::{Result, anyhow, bail, ensure};
+use safetensors::{Dtype, SafeTensors, View};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Magic bytes opening every tensor message, to tell tensors apart from raw messages
+pub const TENSOR_MAGIC: [u8; 4] = *b"PIRX";
+
+/// Length of the fixed-size part of a tensor header
+const HEADER_LEN: usize = 12;
+
+/// Element type of a tensor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DType {
+    Bool,
+    U8,
+    I8,
+    I16,
+    I32,
+    I64,
+    F16,
+    BF16,
+    F32,
+    F64,
+}
+
+impl DType {
+    const ALL: [DType; 10] = [
+        DType::Bool,
+        DType::U8,
+        DType::I8,
+        DType::I16,
+        DType::I32,
+        DType::I64,
+        DType::F16,
+        DType::BF16,
+        DType::F32,
+        DType::F64,
+    ];
+
+    /// Size of an element in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            DType::Bool | DType::U8 | DType::I8 => 1,
+            DType::I16 | DType::F16 | DType::BF16 => 2,
+            DType::I32 | DType::F32 => 4,
+            DType::I64 | DType::F64 => 8,
+        }
+    }
+
+    /// Name of the type as used by numpy and torch
+    pub fn name(&self) -> &'static str {
+        match self {
+            DType::Bool => "bool",
+            DType::U8 => "uint8",
+            DType::I8 => "int8",
+            DType::I16 => "int16",
+            DType::I32 => "int32",
+            DType::I64 => "int64",
+            DType::F16 => "float16",
+            DType::BF16 => "bfloat16",
+            DType::F32 => "float32",
+            DType::F64 => "float64",
+        }
+    }
+
+    fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        Self::ALL
+            .get(code as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown tensor dtype code {}", code))
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|dtype| dtype.name() == s)
+            .ok_or_else(|| anyhow!("Unsupported tensor dtype: {}", s))
+    }
+}
+
+impl From<DType> for Dtype {
+    fn from(dtype: DType) -> Self {
+        match dtype {
+            DType::Bool => Dtype::BOOL,
+            DType::U8 => Dtype::U8,
+            DType::I8 => Dtype::I8,
+            DType::I16 => Dtype::I16,
+            DType::I32 => Dtype::I32,
+            DType::I64 => Dtype::I64,
+            DType::F16 => Dtype::F16,
+            DType::BF16 => Dtype::BF16,
+            DType::F32 => Dtype::F32,
+            DType::F64 => Dtype::F64,
+        }
+    }
+}
+
+impl TryFrom<Dtype> for DType {
+    type Error = anyhow::Error;
+
+    fn try_from(dtype: Dtype) -> Result<Self> {
+        Ok(match dtype {
+            Dtype::BOOL => DType::Bool,
+            Dtype::U8 => DType::U8,
+            Dtype::I8 => DType::I8,
+            Dtype::I16 => DType::I16,
+            Dtype::I32 => DType::I32,
+            Dtype::I64 => DType::I64,
+            Dtype::F16 => DType::F16,
+            Dtype::BF16 => DType::BF16,
+            Dtype::F32 => DType::F32,
+            Dtype::F64 => DType::F64,
+            _ => bail!("Unsupported tensor dtype: {}", dtype),
+        })
+    }
+}
+
+/// Byte order of the elements of a tensor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// Byte order of this machine
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(ByteOrder::Little),
+            1 => Ok(ByteOrder::Big),
+            _ => bail!("Unknown tensor byte order code {}", code),
+        }
+    }
+}
+
+/// Tensor sent as a single message, with the metadata needed to rebuild it on the other
+/// side. Strides are given in bytes, so that any (non-negatively) strided view can be
+/// sent as is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tensor {
+    pub name: Option<String>,
+    pub dtype: DType,
+    pub shape: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub byte_order: ByteOrder,
+    pub data: Vec<u8>,
+}
+
+/// Strides (in bytes) of a C-contiguous tensor, or `None` if the tensor would span more
+/// bytes than fit into the address space
+pub fn contiguous_strides(dtype: DType, shape: &[usize]) -> Option<Vec<usize>> {
+    let mut strides = vec![dtype.size(); shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1].checked_mul(shape[i + 1].max(1))?;
+    }
+    if let (Some(&dim), Some(&stride)) = (shape.first(), strides.first()) {
+        stride.checked_mul(dim.max(1))?;
+    }
+    Some(strides)
+}
+
+impl Tensor {
+    /// Create a C-contiguous tensor in the byte order of this machine
+    pub fn new(dtype: DType, shape: Vec<usize>, data: Vec<u8>) -> Result<Self> {
+        let strides = contiguous_strides(dtype, &shape)
+            .ok_or_else(|| anyhow!("Tensor of shape {:?} is too large", shape))?;
+        let tensor = Self {
+            name: None,
+            dtype,
+            strides,
+            shape,
+            byte_order: ByteOrder::native(),
+            data,
+        };
+        tensor.validate()?;
+        Ok(tensor)
+    }
+
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Number of elements, or `None` if there are more than fit into the address space.
+    /// Empty dimensions count as one for this check, so that they cannot hide other huge
+    /// dimensions
+    pub fn numel(&self) -> Option<usize> {
+        let numel = self
+            .shape
+            .iter()
+            .try_fold(1usize, |numel, &dim| numel.checked_mul(dim.max(1)))?;
+        Some(if self.shape.contains(&0) { 0 } else { numel })
+    }
+
+    pub fn is_contiguous(&self) -> bool {
+        contiguous_strides(self.dtype, &self.shape).is_some_and(|strides| strides == self.strides)
+    }
+
+    // Number of bytes spanned by the elements, given the shape and strides
+    fn extent(&self) -> Option<usize> {
+        if self.numel()? == 0 {
+            return Some(0);
+        }
+        self.shape
+            .iter()
+            .zip(&self.strides)
+            .try_fold(self.dtype.size(), |extent, (&dim, &stride)| {
+                extent.checked_add((dim - 1).checked_mul(stride)?)
+            })
+    }
+
+    /// Check that shape, strides and data are consistent
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.shape.len() == self.strides.len(),
+            "Tensor has {} dimensions but {} strides",
+            self.shape.len(),
+            self.strides.len()
+        );
+        let extent = self
+            .extent()
+            .ok_or_else(|| anyhow!("Tensor of shape {:?} is too large", self.shape))?;
+        ensure!(
+            self.data.len() == extent,
+            "Tensor of shape {:?} and strides {:?} spans {} bytes, got {} bytes of data",
+            self.shape,
+            self.strides,
+            extent,
+            self.data.len()
+        );
+        Ok(())
+    }
+
+    /// Serialize the tensor as magic, dtype (u8), byte order (u8), number of dimensions
+    /// (u16), length of the name (u32), shape and strides (u64 each), name and data (all
+    /// integers are little-endian)
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let name = self.name.as_deref().unwrap_or_default();
+        let ndim = u16::try_from(self.shape.len())
+            .map_err(|_| anyhow!("Tensor has too many dimensions: {}", self.shape.len()))?;
+        let mut msg =
+            Vec::with_capacity(HEADER_LEN + 16 * self.shape.len() + name.len() + self.data.len());
+        msg.extend_from_slice(&TENSOR_MAGIC);
+        msg.push(self.dtype.code());
+        msg.push(self.byte_order as u8);
+        msg.extend_from_slice(&ndim.to_le_bytes());
+        msg.extend_from_slice(&u32::try_from(name.len())?.to_le_bytes());
+        for &dim in self.shape.iter().chain(&self.strides) {
+            msg.extend_from_slice(&(dim as u64).to_le_bytes());
+        }
+        msg.extend_from_slice(name.as_bytes());
+        msg.extend_from_slice(&self.data);
+        Ok(msg)
+    }
+
+    /// Parse a tensor message, reusing its memory for the data of the tensor
+    pub fn decode(mut msg: Vec<u8>) -> Result<Self> {
+        let (mut tensor, data_offset) = Self::decode_header(&msg)?;
+        msg.drain(..data_offset);
+        tensor.data = msg;
+        tensor.validate()?;
+        Ok(tensor)
+    }
+
+    // Parse the header of a tensor message into a tensor without data, and return it
+    // together with the offset of the data
+    pub(crate) fn decode_header(msg: &[u8]) -> Result<(Self, usize)> {
+        ensure!(
+            msg.len() >= HEADER_LEN && msg[..4] == TENSOR_MAGIC,
+            "Message is not a tensor"
+        );
+        let dtype = DType::from_code(msg[4])?;
+        let byte_order = ByteOrder::from_code(msg[5])?;
+        let ndim = u16::from_le_bytes([msg[6], msg[7]]) as usize;
+        let name_len = u32::from_le_bytes(msg[8..12].try_into().unwrap()) as usize;
+        let data_offset = HEADER_LEN + 16 * ndim + name_len;
+        ensure!(msg.len() >= data_offset, "Truncated tensor header");
+
+        let mut dims = msg[HEADER_LEN..HEADER_LEN + 16 * ndim]
+            .chunks_exact(8)
+            .map(|dim| usize::try_from(u64::from_le_bytes(dim.try_into().unwrap())))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("Tensor dimension exceeds the address space"))?;
+        let strides = dims.split_off(ndim);
+        let name = match name_len {
+            0 => None,
+            _ => Some(String::from_utf8(
+                msg[data_offset - name_len..data_offset].to_vec(),
+            )?),
+        };
+
+        let tensor = Self {
+            name,
+            dtype,
+            shape: dims,
+            strides,
+            byte_order,
+            data: Vec::new(),
+        };
+        Ok((tensor, data_offset))
+    }
+
+    /// Convert the tensor into a C-contiguous tensor in the byte order of this machine,
+    /// copying its data only if necessary. The tensor has to be valid (see
+    /// [`Tensor::validate`])
+    pub fn into_native(self) -> Self {
+        self.into_contiguous(ByteOrder::native())
+    }
+
+    /// Convert the tensor into a C-contiguous tensor in the given byte order, copying its
+    /// data only if necessary. The tensor has to be valid (see [`Tensor::validate`])
+    pub fn into_contiguous(self, byte_order: ByteOrder) -> Self {
+        let mut tensor = if self.is_contiguous() {
+            self
+        } else {
+            let data = self.gather();
+            Self {
+                strides: contiguous_strides(self.dtype, &self.shape)
+                    .expect("Valid tensors fit into the address space"),
+                data,
+                ..self
+            }
+        };
+        if tensor.byte_order != byte_order {
+            for element in tensor.data.chunks_exact_mut(tensor.dtype.size()) {
+                element.reverse();
+            }
+            tensor.byte_order = byte_order;
+        }
+        tensor
+    }
+
+    // Copy the elements into a new buffer in C order
+    fn gather(&self) -> Vec<u8> {
+        let size = self.dtype.size();
+        let numel = self
+            .numel()
+            .expect("Valid tensors fit into the address space");
+        let mut data = Vec::with_capacity(numel * size);
+        if numel == 0 {
+            return data;
+        }
+        let mut index = vec![0; self.shape.len()];
+        loop {
+            let offset: usize = index.iter().zip(&self.strides).map(|(i, s)| i * s).sum();
+            data.extend_from_slice(&self.data[offset..offset + size]);
+
+            // Advance the index, starting with the last dimension
+            let mut dim = self.shape.len();
+            loop {
+                if dim == 0 {
+                    return data;
+                }
+                dim -= 1;
+                index[dim] += 1;
+                if index[dim] < self.shape[dim] {
+                    break;
+                }
+                index[dim] = 0;
+            }
+        }
+    }
+}
+
+// Safetensors stores C-contiguous little-endian data, tensors in any other layout are
+// converted while serializing
+impl View for &Tensor {
+    fn dtype(&self) -> Dtype {
+        self.dtype.into()
+    }
+
+    fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        if self.is_contiguous() && self.byte_order == ByteOrder::Little {
+            Cow::Borrowed(&self.data)
+        } else {
+            Cow::Owned((*self).clone().into_contiguous(ByteOrder::Little).data)
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        self.numel()
+            .and_then(|numel| numel.checked_mul(self.dtype.size()))
+            .expect("Valid tensors fit into the address space")
+    }
+}
+
+/// Serialize named tensors as a single message in the safetensors layout, i.e. the size
+/// of a JSON header (a little-endian u64), the header with dtype, shape and data offsets
+/// of every tensor, and their contiguous data
+pub fn encode_tensors(tensors: &[Tensor]) -> Result<Vec<u8>> {
+    let mut names = HashSet::new();
+    for tensor in tensors {
+        let name = tensor
+            .name
+            .as_deref()
+            .ok_or_else(|| anyhow!("Tensors sent together need a name"))?;
+        ensure!(names.insert(name), "Duplicate tensor name: {}", name);
+        tensor.validate()?;
+    }
+    let tensors = tensors
+        .iter()
+        .map(|tensor| (tensor.name.as_deref().unwrap_or_default(), tensor));
+    Ok(safetensors::serialize(tensors, None)?)
+}
+
+/// Parse a message in the safetensors layout, validating its header against the data,
+/// and return its tensors sorted by name
+pub fn decode_tensors(msg: &[u8]) -> Result<Vec<Tensor>> {
+    let mut tensors = SafeTensors::deserialize(msg)?
+        .tensors()
+        .into_iter()
+        .map(|(name, view)| {
+            Ok(Tensor {
+                byte_order: ByteOrder::Little,
+                ..Tensor::new(
+                    view.dtype().try_into()?,
+                    view.shape().to_vec(),
+                    view.data().to_vec(),
+                )?
+                .with_name(name)
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    tensors.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tensors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() -> Result<()> {
+        let data: Vec<u8> = (0..24).collect();
+        let tensor = Tensor::new(DType::F32, vec![2, 3], data)?.with_name("hidden");
+        assert_eq!(tensor.strides, vec![12, 4]);
+        assert_eq!(Tensor::decode(tensor.encode()?)?, tensor);
+
+        // Scalars and empty tensors
+        let scalar = Tensor::new(DType::I64, vec![], vec![1; 8])?;
+        assert_eq!(Tensor::decode(scalar.encode()?)?, scalar);
+        let empty = Tensor::new(DType::BF16, vec![0, 4], vec![])?;
+        assert_eq!(Tensor::decode(empty.encode()?)?, empty);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_tensors() -> Result<()> {
+        assert!(Tensor::new(DType::F32, vec![2, 3], vec![0; 23]).is_err());
+        assert!(Tensor::decode(b"raw message".to_vec()).is_err());
+
+        let msg = Tensor::new(DType::U8, vec![4], vec![0; 4])?.encode()?;
+        assert!(Tensor::decode(msg[..msg.len() - 1].to_vec()).is_err());
+        let mut unknown_dtype = msg.clone();
+        unknown_dtype[4] = 255;
+        assert!(Tensor::decode(unknown_dtype).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflowing_shapes() -> Result<()> {
+        // Shapes whose size (or strides) overflow, even if an empty dimension leaves them
+        // without any elements
+        for shape in [
+            vec![1 << 62, 4],
+            vec![1 << 32, 1 << 32],
+            vec![1 << 40, 1 << 40, 0],
+        ] {
+            assert!(Tensor::new(DType::U8, shape.clone(), Vec::new()).is_err());
+            let tensor = Tensor {
+                name: None,
+                dtype: DType::U8,
+                strides: vec![1; shape.len()],
+                shape,
+                byte_order: ByteOrder::native(),
+                data: Vec::new(),
+            };
+            assert_eq!(tensor.numel(), None);
+            assert!(!tensor.is_contiguous());
+            assert!(tensor.validate().is_err());
+
+            // Messages with such shapes are rejected as well
+            let msg = tensor.encode()?;
+            assert!(Tensor::decode(msg).is_err());
+        }
+        assert!(contiguous_strides(DType::F32, &[1 << 62]).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dtype_names() {
+        for dtype in DType::ALL {
+            assert_eq!(dtype.name().parse::<DType>().unwrap(), dtype);
+            assert_eq!(DType::from_code(dtype.code()).unwrap(), dtype);
+        }
+        assert!("complex64".parse::<DType>().is_err());
+    }
+
+    #[test]
+    fn test_into_native() -> Result<()> {
+        // Transposed 2x3 view of a 3x2 tensor of u16
+        let data: Vec<u8> = [1u16, 2, 3, 4, 5, 6]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect();
+        let tensor = Tensor {
+            name: None,
+            dtype: DType::I16,
+            shape: vec![2, 3],
+            strides: vec![2, 4],
+            byte_order: ByteOrder::Big,
+            data,
+        };
+        tensor.validate()?;
+
+        let native = tensor.into_native();
+        assert!(native.is_contiguous());
+        assert_eq!(native.byte_order, ByteOrder::native());
+        let values: Vec<u16> = native
+            .data
+            .chunks_exact(2)
+            .map(|x| u16::from_ne_bytes([x[0], x[1]]))
+            .collect();
+        assert_eq!(values, vec![1, 3, 5, 2, 4, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tensors_roundtrip() -> Result<()> {
+        let hidden = Tensor::new(DType::BF16, vec![2, 4], vec![1; 16])?.with_name("hidden");
+        let mask = Tensor::new(DType::Bool, vec![2], vec![1, 0])?.with_name("mask");
+        let msg = encode_tensors(&[mask.clone(), hidden.clone()])?;
+
+        // Readable by any safetensors implementation
+        let header_len = u64::from_le_bytes(msg[..8].try_into()?) as usize;
+        assert!(std::str::from_utf8(&msg[8..8 + header_len])?.contains("\"BF16\""));
+        assert_eq!(decode_tensors(&msg)?, vec![hidden, mask]);
+
+        // Non-contiguous and big-endian tensors are converted
+        let transposed = Tensor {
+            name: Some("transposed".to_string()),
+            dtype: DType::I16,
+            shape: vec![2, 2],
+            strides: vec![2, 4],
+            byte_order: ByteOrder::Big,
+            data: vec![0, 1, 0, 2, 0, 3, 0, 4],
+        };
+        let recv = decode_tensors(&encode_tensors(&[transposed])?)?;
+        assert_eq!(recv[0].data, vec![1, 0, 3, 0, 2, 0, 4, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_tensors_messages() -> Result<()> {
+        let tensor = Tensor::new(DType::F32, vec![2], vec![0; 8])?;
+        assert!(encode_tensors(std::slice::from_ref(&tensor)).is_err());
+        let tensor = tensor.with_name("x");
+        assert!(encode_tensors(&[tensor.clone(), tensor.clone()]).is_err());
+
+        // Headers that don't match the data are rejected
+        let msg = encode_tensors(&[tensor])?;
+        assert!(decode_tensors(&msg[..msg.len() - 1]).is_err());
+        assert!(decode_tensors(&[msg.clone(), vec![0; 4]].concat()).is_err());
+        assert!(decode_tensors(b"not safetensors").is_err());
+        Ok(())
+    }
+}
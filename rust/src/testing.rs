@@ -0,0 +1,92 @@
+//! Helpers for unit tests that connect senders and receivers over localhost
+
+use anyhow::{Error, Result};
+use iroh::endpoint::{Connection, SendStream};
+use iroh::{Endpoint, NodeAddr, NodeId, RelayMode, SecretKey};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+use crate::handshake::{Handshake, transport_config};
+use crate::node::NodeConfig;
+use crate::receiver::{ALPN, Receiver};
+use crate::retry::RetryPolicy;
+use crate::sender::Sender;
+
+pub(crate) const SENDER_SEED: u64 = 42;
+
+// Endpoint reachable over localhost only, with a fixed node id if a seed is given
+pub(crate) async fn bind_offline(seed: Option<u64>) -> Result<Endpoint> {
+    let mut builder = Endpoint::builder()
+        .transport_config(transport_config())
+        .relay_mode(RelayMode::Disabled);
+    if let Some(seed) = seed {
+        builder = builder.secret_key(SecretKey::generate(&mut StdRng::seed_from_u64(seed)));
+    }
+    builder.bind().await
+}
+
+// Node id of the endpoints bound with `SENDER_SEED`
+pub(crate) fn sender_id() -> NodeId {
+    SecretKey::generate(&mut StdRng::seed_from_u64(SENDER_SEED)).public()
+}
+
+// Receiver on localhost, and the endpoint of a sender that did not connect yet
+pub(crate) struct LocalPeers {
+    pub runtime: Arc<Runtime>,
+    pub receiver: Receiver,
+    pub send_endpoint: Endpoint,
+    pub recv_addr: NodeAddr,
+}
+
+impl LocalPeers {
+    pub fn bind(config: &NodeConfig, sender_seed: Option<u64>) -> Result<Self> {
+        let runtime = Arc::new(Runtime::new()?);
+        let (recv_endpoint, send_endpoint) = runtime.block_on(async {
+            Ok::<_, Error>((bind_offline(None).await?, bind_offline(sender_seed).await?))
+        })?;
+        let recv_addr = runtime.block_on(recv_endpoint.node_addr())?;
+        let receiver = Receiver::with_config(runtime.clone(), recv_endpoint, config);
+        Ok(Self {
+            runtime,
+            receiver,
+            send_endpoint,
+            recv_addr,
+        })
+    }
+
+    // Sender on the endpoint of the sender, not connected yet
+    pub fn sender(&self, config: &NodeConfig) -> Sender {
+        Sender::with_config(self.runtime.clone(), self.send_endpoint.clone(), config)
+    }
+
+    // Connection to the receiver, without any handshake
+    pub async fn connect(&self) -> Result<Connection> {
+        self.send_endpoint
+            .connect(self.recv_addr.clone(), ALPN)
+            .await
+    }
+
+    // Connection to the receiver with a handshake for one stream, which is returned along
+    // with the acknowledgement of the receiver
+    pub async fn handshake(&self) -> Result<(Connection, SendStream, Handshake)> {
+        let connection = self.connect().await?;
+        let mut stream = connection.open_uni().await?;
+        Handshake::new(0, 1, Vec::new())?.write(&mut stream).await?;
+        let ack = Handshake::read(&mut connection.accept_uni().await?).await?;
+        Ok((connection, stream, ack))
+    }
+}
+
+// Connect a sender to a receiver over localhost, without discovery
+pub(crate) fn connect_offline(config: &NodeConfig) -> Result<(Receiver, Sender, NodeId)> {
+    let peers = LocalPeers::bind(config, Some(SENDER_SEED))?;
+    let mut sender = peers.sender(config);
+    sender.connect_addr(peers.recv_addr, config.num_streams, &RetryPolicy::new(10))?;
+    while !peers.receiver.is_ready() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok((peers.receiver, sender, sender_id()))
+}
@@ -1,5 +1,5 @@
-use anyhow::Result;
-use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, OnceLock};
 use tokio::runtime::Runtime;
 use tokio::task::JoinHandle;
 
@@ -18,19 +18,82 @@ impl SendWork {
     }
 }
 
-pub struct RecvWork {
+pub struct ConnectWork {
     pub runtime: Arc<Runtime>,
-    pub handle: JoinHandle<Result<Vec<u8>>>,
+    pub handle: JoinHandle<Result<()>>,
 }
 
-impl RecvWork {
-    pub fn new(runtime: Arc<Runtime>, handle: JoinHandle<Result<Vec<u8>>>) -> Self {
+impl ConnectWork {
+    pub fn new(runtime: Arc<Runtime>, handle: JoinHandle<Result<()>>) -> Self {
         Self { runtime, handle }
     }
 
-    pub fn wait(self) -> Result<Vec<u8>> {
+    pub fn is_completed(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    pub fn wait(self) -> Result<()> {
+        self.runtime.block_on(self.handle)?
+    }
+}
+
+/// Work of a receive, resolving to the received message (or the buffer that the message
+/// was received into)
+pub struct RecvWork<T = Vec<u8>> {
+    pub runtime: Arc<Runtime>,
+    pub handle: JoinHandle<Result<T>>,
+    /// Sequence number of the message among the messages of its tag, set once the
+    /// message has been received
+    pub seq: Arc<OnceLock<u64>>,
+}
+
+impl<T> RecvWork<T> {
+    pub fn new(runtime: Arc<Runtime>, handle: JoinHandle<Result<T>>) -> Self {
+        Self {
+            runtime,
+            handle,
+            seq: Arc::default(),
+        }
+    }
+
+    pub fn wait(self) -> Result<T> {
         self.runtime.block_on(self.handle)?
     }
+
+    /// Wait for the message and return it together with its sequence number
+    pub fn wait_with_seq(self) -> Result<(T, u64)> {
+        let seq = self.seq.clone();
+        let msg = self.wait()?;
+        let seq = seq
+            .get()
+            .copied()
+            .ok_or_else(|| anyhow!("Message has no sequence number"))?;
+        Ok((msg, seq))
+    }
+}
+
+impl<T: Send + 'static> RecvWork<T> {
+    /// Transform the received message once it arrives (off the runtime threads, since
+    /// e.g. decoding it takes a while for large messages)
+    pub fn map<U: Send + 'static>(
+        self,
+        f: impl FnOnce(T) -> Result<U> + Send + 'static,
+    ) -> RecvWork<U> {
+        let Self {
+            runtime,
+            handle,
+            seq,
+        } = self;
+        let handle = runtime.spawn(async move {
+            let msg = handle.await??;
+            tokio::task::spawn_blocking(move || f(msg)).await?
+        });
+        RecvWork {
+            runtime,
+            handle,
+            seq,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -47,10 +110,7 @@ mod tests {
             Ok(b"test".to_vec())
         });
         
-        let work = RecvWork {
-            runtime,
-            handle,
-        };
+        let work = RecvWork::new(runtime, handle);
         
         let result = work.wait();
         
@@ -65,10 +125,7 @@ mod tests {
             Err(Error::msg("test error"))
         });
         
-        let work = RecvWork {
-            runtime,
-            handle,
-        };
+        let work: RecvWork = RecvWork::new(runtime, handle);
         
         let result = work.wait();
         
@@ -76,6 +133,58 @@ mod tests {
         assert_eq!(result.unwrap_err().to_string(), "test error");
     }
 
+    #[test]
+    fn test_work_with_seq() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let work = RecvWork::new(runtime.clone(), runtime.spawn(async { Ok(b"test".to_vec()) }));
+        work.seq.set(7).unwrap();
+        assert_eq!(work.wait_with_seq().unwrap(), (b"test".to_vec(), 7));
+
+        // Messages that were not received have no sequence number
+        let work = RecvWork::new(runtime.clone(), runtime.spawn(async { Ok(b"test".to_vec()) }));
+        assert!(work.wait_with_seq().is_err());
+    }
+
+    #[test]
+    fn test_map_off_runtime_threads() {
+        let runtime = Arc::new(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+                .unwrap(),
+        );
+        let (started, start) = std::sync::mpsc::channel();
+        let work = RecvWork::new(runtime.clone(), runtime.spawn(async { Ok(1) })).map(move |n| {
+            started.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(n + 1)
+        });
+
+        // The only runtime thread stays free for other tasks meanwhile
+        start.recv().unwrap();
+        let begin = Instant::now();
+        runtime.block_on(runtime.spawn(async {})).unwrap();
+        assert!(begin.elapsed() < Duration::from_millis(250));
+        assert_eq!(work.wait().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_connect_work_is_completed() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let handle = runtime.spawn(async {
+            sleep(Duration::from_millis(100)).await;
+            Ok(())
+        });
+
+        let work = ConnectWork::new(runtime, handle);
+        assert!(!work.is_completed());
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(work.is_completed());
+        assert!(work.wait().is_ok());
+    }
+
     #[test]
     fn test_work_with_delay() {
         let runtime = Arc::new(Runtime::new().unwrap());
@@ -84,10 +193,7 @@ mod tests {
             Ok(b"test".to_vec())
         });
 
-        let work = RecvWork {
-            runtime,
-            handle,
-        };
+        let work = RecvWork::new(runtime, handle);
         
         let start = Instant::now();
         let result = work.wait();
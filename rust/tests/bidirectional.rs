@@ -1,5 +1,5 @@
 use anyhow::Result;
-use prime_iroh::node::Node;
+use prime_iroh::node::{Node, NodeConfig};
 use std::time::Duration;
 
 const NUM_MESSAGES: usize = 5;
@@ -12,10 +12,10 @@ struct BidirectionalTest {
 
 impl BidirectionalTest {
     fn new() -> Result<Self> {
-        // Initialize nodes
-        let mut node0 = Node::with_seed(NUM_STREAMS, None)?;
+        // Initialize nodes (offline, so that the test does not depend on discovery)
+        let mut node0 = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
         println!("Initializing node 0 (ID: {})", node0.node_id());
-        let mut node1 = Node::with_seed(NUM_STREAMS, None)?;
+        let mut node1 = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
         println!("Initializing node 1 (ID: {})", node1.node_id());
 
         // Wait for nodes to initialize (only necessary in single process tests)
@@ -27,13 +27,13 @@ impl BidirectionalTest {
             node0.node_id(),
             node1.node_id()
         );
-        node0.connect(node1.node_id(), 10)?;
+        node0.connect(node1.ticket()?, 10)?;
         println!(
             "Connecting node 1->0 (ID: {}->{})",
             node1.node_id(),
             node0.node_id()
         );
-        node1.connect(node0.node_id(), 10)?;
+        node1.connect(node0.ticket()?, 10)?;
 
         while !node0.can_recv() || !node1.can_send() {
             std::thread::sleep(std::time::Duration::from_millis(100));
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use std::time::Duration;
 
-use prime_iroh::node::Node;
+use prime_iroh::node::{Discovery, Node, NodeConfig};
 
 const NUM_STREAMS: usize = 1;
 
@@ -11,11 +11,21 @@ struct ConnectionTest {
 
 impl ConnectionTest {
     fn new(num_nodes: usize) -> Result<Self> {
-        // Initialize nodes
+        // Initialize nodes (with local network discovery, so that the test does not depend on internet access)
+        Self::with_nodes(num_nodes, || {
+            Node::with_config(NodeConfig {
+                discovery: Discovery::Local,
+                relay: false,
+                ..NodeConfig::new(NUM_STREAMS)
+            })
+        })
+    }
+
+    fn with_nodes(num_nodes: usize, new_node: impl Fn() -> Result<Node>) -> Result<Self> {
         let mut nodes = Vec::new();
         let mut node_ids = Vec::new();
         for i in 0..num_nodes {
-            let node = Node::new(NUM_STREAMS)?;
+            let node = new_node()?;
             let node_id = node.node_id();
             println!("Initializing node {} (ID: {})", i, node_id);
             nodes.push(node);
@@ -25,9 +35,9 @@ impl ConnectionTest {
         // Wait for nodes to initialize (only necessary in single process tests)
         std::thread::sleep(Duration::from_millis(1000));
 
-        // Connect nodes
-        for i in 0..num_nodes {
-            let current_node = &mut nodes[i];
+        // Connect nodes concurrently
+        let mut connect_works = Vec::new();
+        for (i, current_node) in nodes.iter_mut().enumerate() {
             let j = (i + 1) % num_nodes;
             let node_id = current_node.node_id();
             let peer_id = node_ids[j].clone();
@@ -35,7 +45,10 @@ impl ConnectionTest {
                 "Connecting node {}->{} (ID: {}->{})",
                 i, j, node_id, peer_id
             );
-            current_node.connect(peer_id, 10)?;
+            connect_works.push(current_node.iconnect(peer_id, 10)?);
+        }
+        for connect_work in connect_works {
+            connect_work.wait()?;
         }
 
         while !nodes.iter().all(|node| node.is_ready()) {
@@ -94,4 +107,23 @@ mod tests {
 
         Ok(())
     }
+
+    // Default nodes, which find each other by node ID via n0 discovery and relays
+    #[test]
+    #[ignore = "requires internet access, run with `cargo test -- --ignored`"]
+    fn test_connection_via_n0_discovery() -> Result<()> {
+        let num_nodes = 2;
+        let mut test = ConnectionTest::with_nodes(num_nodes, || Node::new(NUM_STREAMS))?;
+
+        // Test connection state and a message over the connection
+        test.verify_active_connection_state()?;
+        test.nodes[0].isend(b"via n0".to_vec(), 0, None)?.wait()?;
+        assert_eq!(test.nodes[1].irecv(0)?.wait()?, b"via n0".to_vec());
+
+        // Teardown
+        test.teardown()?;
+        test.verify_inactive_connection_state()?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use prime_iroh::node::{Node, NodeConfig};
+use std::time::Duration;
+
+const NUM_MESSAGES: usize = 5;
+const NUM_STREAMS: usize = 1;
+
+struct MultiPeerTest {
+    hub: Node,
+    spokes: Vec<Node>,
+}
+
+impl MultiPeerTest {
+    fn new(num_spokes: usize) -> Result<Self> {
+        // Initialize hub (offline, so that the test does not depend on discovery)
+        let mut hub = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
+        println!("Initialized hub (ID: {})", hub.node_id());
+
+        // Initialize spokes
+        let mut spokes = Vec::new();
+        for i in 0..num_spokes {
+            let spoke = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
+            println!("Initialized spoke {} (ID: {})", i, spoke.node_id());
+            spokes.push(spoke);
+        }
+
+        // Wait for nodes to initialize (only necessary in single process tests)
+        std::thread::sleep(Duration::from_millis(1000));
+
+        // Connect hub and spokes bidirectionally
+        for (i, spoke) in spokes.iter_mut().enumerate() {
+            println!(
+                "Connecting hub<->spoke {} (ID: {}<->{})",
+                i,
+                hub.node_id(),
+                spoke.node_id()
+            );
+            hub.connect_addr(spoke.node_addr()?, 10)?;
+            spoke.connect_addr(hub.node_addr()?, 10)?;
+        }
+
+        // Wait for all connections to be established
+        while hub.recv_peers().len() < num_spokes || !spokes.iter().all(|spoke| spoke.is_ready()) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(Self { hub, spokes })
+    }
+
+    fn test_peers(&self) -> Result<()> {
+        let mut spoke_ids: Vec<String> = self.spokes.iter().map(|spoke| spoke.node_id()).collect();
+        spoke_ids.sort();
+
+        let mut send_peers = self.hub.send_peers();
+        send_peers.sort();
+        assert_eq!(send_peers, spoke_ids);
+
+        let mut recv_peers = self.hub.recv_peers();
+        recv_peers.sort();
+        assert_eq!(recv_peers, spoke_ids);
+
+        Ok(())
+    }
+
+    fn test_ambiguous_peer(&mut self) -> Result<()> {
+        // Hub has multiple peers, so the peer has to be specified explicitly
+        assert!(self.hub.isend(b"ambiguous".to_vec(), 0, None).is_err());
+        assert!(self.hub.irecv(0).is_err());
+
+        Ok(())
+    }
+
+    fn test_communication(&mut self) -> Result<()> {
+        for i in 0..NUM_MESSAGES {
+            // Hub sends a different message to each spoke
+            for (j, spoke) in self.spokes.iter().enumerate() {
+                let msg = format!("Message {} from hub to spoke {}", i, j);
+                self.hub
+                    .isend_to(&spoke.node_id(), msg.as_bytes().to_vec(), 0, None)?
+                    .wait()?;
+            }
+
+            // Each spoke receives its message and replies
+            for (j, spoke) in self.spokes.iter_mut().enumerate() {
+                let received = spoke.irecv(0)?.wait()?;
+                assert_eq!(
+                    received,
+                    format!("Message {} from hub to spoke {}", i, j).into_bytes()
+                );
+
+                let msg = format!("Message {} from spoke {} to hub", i, j);
+                spoke.isend(msg.as_bytes().to_vec(), 0, None)?.wait()?;
+            }
+
+            // Hub receives the reply from each spoke
+            for (j, spoke) in self.spokes.iter().enumerate() {
+                let received = self.hub.irecv_from(&spoke.node_id(), 0)?.wait()?;
+                assert_eq!(
+                    received,
+                    format!("Message {} from spoke {} to hub", i, j).into_bytes()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn teardown(&mut self) -> Result<()> {
+        self.hub.close()?;
+        for spoke in self.spokes.iter_mut() {
+            spoke.close()?;
+        }
+        Ok(())
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_peer_communication() -> Result<()> {
+        let mut test = MultiPeerTest::new(2)?;
+
+        // Test peer bookkeeping
+        test.test_peers()?;
+
+        // Test that implicit peer selection fails with multiple peers
+        test.test_ambiguous_peer()?;
+
+        // Test communication with each peer
+        test.test_communication()?;
+
+        test.teardown()?;
+
+        Ok(())
+    }
+}
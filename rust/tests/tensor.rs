@@ -0,0 +1,64 @@
+use anyhow::Result;
+use prime_iroh::node::{Node, NodeConfig};
+use prime_iroh::tensor::{DType, Tensor};
+use std::time::Duration;
+
+const NUM_STREAMS: usize = 1;
+
+struct TensorTest {
+    sender: Node,
+    receiver: Node,
+}
+
+impl TensorTest {
+    fn new() -> Result<Self> {
+        // Initialize nodes (offline, so that the test does not depend on discovery)
+        let receiver = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
+        let mut sender = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
+
+        // Wait for nodes to initialize (only necessary in single process tests)
+        std::thread::sleep(Duration::from_millis(1000));
+
+        // Connect sender to receiver
+        sender.connect_addr(receiver.node_addr()?, 10)?;
+        while !receiver.can_recv() || !sender.can_send() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(Self { sender, receiver })
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_communication() -> Result<()> {
+        let mut test = TensorTest::new()?;
+
+        // Tensors arrive with their metadata
+        let data: Vec<u8> = (0..16u32).flat_map(|x| (x as f32).to_ne_bytes()).collect();
+        let tensor = Tensor::new(DType::F32, vec![4, 4], data)?.with_name("activations");
+        test.sender.isend_tensor(&tensor, 0, None)?.wait()?;
+        let recv = test.receiver.irecv_tensor(0)?.wait()?;
+        assert_eq!(recv, tensor);
+
+        // Raw messages are not mistaken for tensors
+        test.sender.isend(b"not a tensor", 0, None)?.wait()?;
+        assert!(test.receiver.irecv_tensor(0)?.wait().is_err());
+
+        // Several named tensors arrive in one message
+        let mask = Tensor::new(DType::Bool, vec![4], vec![1, 1, 0, 0])?.with_name("mask");
+        let hidden = tensor.with_name("hidden");
+        test.sender
+            .isend_tensors(&[mask.clone(), hidden.clone()], 0, None)?
+            .wait()?;
+        let recv = test.receiver.irecv_tensors(0)?.wait()?;
+        assert_eq!(recv, vec![hidden, mask]);
+
+        test.sender.close()?;
+        test.receiver.close()?;
+
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use prime_iroh::node::Node;
+use prime_iroh::node::{Node, NodeConfig};
 use std::time::Duration;
 
 const NUM_MESSAGES: usize = 5;
@@ -12,12 +12,12 @@ struct UnidirectionalTest {
 
 impl UnidirectionalTest {
     fn new() -> Result<Self> {
-        // Initialize receiver
-        let receiver = Node::new(NUM_STREAMS)?;
+        // Initialize receiver (offline, so that the test does not depend on discovery)
+        let receiver = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
         println!("Initialized receiver (ID: {})", receiver.node_id());
 
         // Initialize sender
-        let mut sender = Node::new(NUM_STREAMS)?;
+        let mut sender = Node::with_config(NodeConfig::offline(NUM_STREAMS))?;
         println!("Initialized sender (ID: {})", sender.node_id());
 
         // Wait for nodes to initialize (only necessary in single process tests)
@@ -29,7 +29,7 @@ impl UnidirectionalTest {
             sender.node_id(),
             receiver.node_id()
         );
-        sender.connect(receiver.node_id(), 10)?;
+        sender.connect_addr(receiver.node_addr()?, 10)?;
 
         // Wait for connection to be established
         while !receiver.can_recv() || !sender.can_send() {